@@ -0,0 +1,109 @@
+//! Streams a generated corpus through `CooccurrenceCounter` and through the
+//! hash map lookup of every pair the counting used before the alphabet lookup
+//! tables, both on a single thread.
+//!
+//! cargo bench --bench lookup
+//!
+//! GATHER_BENCH_BYTES changes the size of the corpus (default 1 GiB), it is
+//! written to a temporary file in TMPDIR.
+
+use character_gather::{CooccurrenceCounter, ReadOptions, Source};
+use ndarray::Array3;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const OFFSET_BACK: usize = 4;
+const OFFSET_FRONT: usize = 4;
+
+fn main() -> character_gather::Result<()> {
+    let size: usize = std::env::var("GATHER_BENCH_BYTES")
+        .map(|bytes| bytes.parse().expect("GATHER_BENCH_BYTES is not a number"))
+        .unwrap_or(1 << 30);
+    let acceptable_types: Vec<char> = "abcdefghijklmnopqrstuvwxyz .".chars().collect();
+
+    let corpus = tempfile::NamedTempFile::new()?;
+    write_corpus(corpus.path(), size)?;
+
+    let now = Instant::now();
+    let lookup = CooccurrenceCounter::new(acceptable_types.clone())
+        .offset_back(OFFSET_BACK)
+        .offset_front(OFFSET_FRONT)
+        .read_options(ReadOptions::new().threads(1))
+        .count(&[Source::new(File::open(corpus.path())?, false)?])?;
+    let lookup_time = now.elapsed();
+
+    let now = Instant::now();
+    let hashed = count_hashed(corpus.path(), &acceptable_types)?;
+    let hashed_time = now.elapsed();
+
+    assert_eq!(lookup, hashed);
+    let throughput = |time: Duration| size as f64 / (1 << 20) as f64 / time.as_secs_f64();
+    println!(
+        "lookup: {lookup_time:.2?} ({:.1} MiB/s), hashed: {hashed_time:.2?} ({:.1} MiB/s), speedup {:.1}x",
+        throughput(lookup_time),
+        throughput(hashed_time),
+        hashed_time.as_secs_f64() / lookup_time.as_secs_f64()
+    );
+    Ok(())
+}
+
+// Mostly lowercase letters, some characters outside of the alphabet
+fn write_corpus(path: &Path, size: usize) -> std::io::Result<()> {
+    let symbols = b"abcdefghijklmnopqrstuvwxyz     ..,;ABC019\n";
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut state: u64 = 0xbe4c4;
+    for _ in 0..size {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        writer.write_all(&[symbols[(state % symbols.len() as u64) as usize]])?;
+    }
+    writer.flush()
+}
+
+fn count_hashed(path: &Path, acceptable_types: &[char]) -> std::io::Result<Array3<u64>> {
+    let index: HashMap<char, usize> = acceptable_types
+        .iter()
+        .enumerate()
+        .map(|(index, &c)| (c, index))
+        .collect();
+    let len = acceptable_types.len();
+    let mut counts = Array3::<u64>::zeros((len, len, OFFSET_BACK + OFFSET_FRONT + 1));
+
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 1 << 16];
+    // The characters that are still needed, `done` of them are counted already
+    let mut text: Vec<char> = Vec::new();
+    let mut done = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        text.extend(buffer[..read].iter().map(|&byte| byte as char));
+        let end = match read {
+            0 => text.len(),
+            _ => text.len().saturating_sub(OFFSET_FRONT),
+        };
+        for centre in done..end {
+            let Some(&base) = index.get(&text[centre]) else {
+                continue;
+            };
+            let last = (centre + OFFSET_FRONT).min(text.len() - 1);
+            for position in centre.saturating_sub(OFFSET_BACK)..=last {
+                if position == centre {
+                    continue;
+                }
+                if let Some(&found) = index.get(&text[position]) {
+                    counts[(base, found, position + OFFSET_BACK - centre)] += 1;
+                }
+            }
+        }
+        if read == 0 {
+            return Ok(counts);
+        }
+        let keep = end.saturating_sub(OFFSET_BACK);
+        text.drain(..keep);
+        done = end - keep;
+    }
+}
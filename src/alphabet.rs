@@ -0,0 +1,243 @@
+use crate::error::{Error, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::path::Path;
+
+/// Index of every character that is not part of the alphabet
+pub const OUTSIDE: u32 = u32::MAX;
+
+/// How the unknown character, which stands for every character outside of
+/// the alphabet, is written out as text
+pub const UNKNOWN: &str = "<UNK>";
+
+/// Maps characters onto their dense index in the alphabet without hashing.
+///
+/// Latin-1 bytes use a fixed 256 entry table, characters of the basic
+/// multilingual plane a table that is only as long as the largest character
+/// of the alphabet, everything above is searched in a short sorted list.
+pub struct Alphabet {
+    characters: Vec<char>,
+    outside: u32,
+    bytes: [u32; 256],
+    basic: Vec<u32>,
+    supplementary: Vec<(char, u32)>,
+}
+
+impl Alphabet {
+    pub fn new(characters: Vec<char>) -> Alphabet {
+        Alphabet::build(characters, OUTSIDE)
+    }
+
+    /// An alphabet where every character outside of it has the index right
+    /// after the last character, the unknown character.
+    pub fn with_unknown(characters: Vec<char>) -> Alphabet {
+        let unknown = characters.len() as u32;
+        Alphabet::build(characters, unknown)
+    }
+
+    fn build(characters: Vec<char>, outside: u32) -> Alphabet {
+        let mut bytes = [outside; 256];
+        let basic_len = characters
+            .iter()
+            .map(|&c| c as usize + 1)
+            .filter(|&len| len <= 0x10000)
+            .max()
+            .unwrap_or(0);
+        let mut basic = vec![outside; basic_len];
+        let mut supplementary: Vec<(char, u32)> = Vec::new();
+
+        for (index, &character) in characters.iter().enumerate() {
+            let index = index as u32;
+            let code = character as usize;
+            if code < 256 {
+                bytes[code] = index;
+            }
+            if code < 0x10000 {
+                basic[code] = index;
+            } else {
+                supplementary.retain(|(c, _)| *c != character);
+                supplementary.push((character, index));
+            }
+        }
+        supplementary.sort_unstable();
+
+        Alphabet {
+            characters,
+            outside,
+            bytes,
+            basic,
+            supplementary,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.characters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.characters.is_empty()
+    }
+
+    pub fn index(&self, character: char) -> u32 {
+        let code = character as usize;
+        if code < 0x10000 {
+            return *self.basic.get(code).unwrap_or(&self.outside);
+        }
+        match self
+            .supplementary
+            .binary_search_by_key(&character, |(c, _)| *c)
+        {
+            Ok(position) => self.supplementary[position].1,
+            Err(_) => self.outside,
+        }
+    }
+
+    pub fn index_bytes(&self, bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .iter()
+            .map(|&byte| self.bytes[byte as usize])
+            .collect()
+    }
+
+    pub fn index_chars(&self, characters: &[char]) -> Vec<u32> {
+        characters.iter().map(|&c| self.index(c)).collect()
+    }
+
+    pub fn index_str(&self, text: &str) -> Vec<u32> {
+        text.chars().map(|c| self.index(c)).collect()
+    }
+}
+
+/// Alphabets that are needed again and again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// a to z
+    LowercaseLatin,
+    /// Every printable ASCII character from space to ~
+    AsciiPrintable,
+    /// a to z, ä, ö, ü and ß
+    German,
+    /// 0 to 9
+    Digits,
+    /// The ASCII punctuation characters !"#$%&'()*+,-./:;<=>?@[\]^_`{|}~
+    Punctuation,
+}
+
+impl Preset {
+    pub fn characters(&self) -> Vec<char> {
+        match self {
+            Preset::LowercaseLatin => ('a'..='z').collect(),
+            Preset::AsciiPrintable => (' '..='~').collect(),
+            Preset::German => ('a'..='z').chain("äöüß".chars()).collect(),
+            Preset::Digits => ('0'..='9').collect(),
+            Preset::Punctuation => (' '..='~').filter(|c| c.is_ascii_punctuation()).collect(),
+        }
+    }
+}
+
+/// Parses an alphabet file, which has one character per line. A character
+/// can also be written as one of the escapes `\n`, `\r`, `\t`, `\s` (space),
+/// `\\` or `\u{2014}`, empty lines are skipped.
+///
+/// ```
+/// use character_gather::alphabet::parse_alphabet;
+///
+/// assert_eq!(parse_alphabet("a\n,\n\\n\n\\u{e4}\n")?, vec!['a', ',', '\n', 'ä']);
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+pub fn parse_alphabet(text: &str) -> Result<Vec<char>> {
+    let mut characters = Vec::new();
+    for (number, line) in text.split('\n').enumerate() {
+        // Windows line endings, a carriage return has to be written as \r
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let character = unescape(line).ok_or_else(|| {
+            Error::Validation(format!(
+                "Line {} of the alphabet file: {line:?} is neither one character nor an escape like \\n or \\u{{2014}}",
+                number + 1
+            ))
+        })?;
+        characters.push(character);
+    }
+    Ok(characters)
+}
+
+/// Reads an alphabet file, see [`parse_alphabet`].
+pub fn read_alphabet_file(path: &Path) -> Result<Vec<char>> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        Error::Io(std::io::Error::new(
+            e.kind(),
+            format!("{}: {e}", path.display()),
+        ))
+    })?;
+    parse_alphabet(&text)
+}
+
+/// Writes an alphabet as an alphabet file, one character per line and
+/// escaped where necessary, which [`parse_alphabet`] reads back.
+///
+/// ```
+/// use character_gather::alphabet::{format_alphabet, parse_alphabet};
+///
+/// let alphabet = vec!['a', ' ', '\n', '/'];
+/// assert_eq!(format_alphabet(&alphabet), "a\n\\s\n\\n\n/\n");
+/// assert_eq!(parse_alphabet(&format_alphabet(&alphabet))?, alphabet);
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+pub fn format_alphabet(characters: &[char]) -> String {
+    characters
+        .iter()
+        .map(|&character| escape(character) + "\n")
+        .collect()
+}
+
+fn unescape(line: &str) -> Option<char> {
+    match unescape_characters(line)?[..] {
+        [character] => Some(character),
+        _ => None,
+    }
+}
+
+/// Reads characters written like in alphabet files, a lone backslash at the
+/// end stands for itself.
+pub(crate) fn unescape_characters(text: &str) -> Option<Vec<char>> {
+    let mut characters = Vec::new();
+    let mut chars = text.chars();
+    while let Some(character) = chars.next() {
+        if character != '\\' {
+            characters.push(character);
+            continue;
+        }
+        characters.push(match chars.next() {
+            None | Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('s') => ' ',
+            Some('u') => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let (code, rest) = rest.split_once('}')?;
+                chars = rest.chars();
+                char::from_u32(u32::from_str_radix(code, 16).ok()?)?
+            }
+            Some(_) => return None,
+        });
+    }
+    Some(characters)
+}
+
+/// Writes a character so that [`unescape_characters`] reads it back, also
+/// when it is whitespace or invisible.
+pub(crate) fn escape(character: char) -> String {
+    match character {
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        ' ' => "\\s".to_string(),
+        c if c.is_whitespace() || c.is_control() => format!("\\u{{{:x}}}", c as u32),
+        c => c.to_string(),
+    }
+}
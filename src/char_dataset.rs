@@ -1,73 +1,280 @@
+use crate::alphabet::{Alphabet, OUTSIDE, UNKNOWN};
+use crate::chunk::{plan, read_chunk, validate_chunksize, Characters, Progress, ReadOptions};
+use crate::error::{Error, Result};
+use crate::metadata;
+use crate::source::Source;
+use clap::ValueEnum;
+use ndarray::{s, Array2};
+use rayon::prelude::*;
 use std::fs::File;
 use std::io::Write;
-use std::io::{self, Read, Seek, SeekFrom};
-use std::sync::{mpsc, Arc};
-use std::thread;
-
-const CHUNKSIZE: usize = 4096 * 4;
-
-pub fn gather_dataset(
-    search_char: char,
-    acceptable_types: Arc<Vec<char>>,
-    offset_back: isize,
-    offset_front: isize,
-    mut file: File,
-    mut result_file: File,
-)
-// -> Vec<Vec<char>>
-{
-    let file_size = file.metadata().expect("Could not read file metadata").len();
-    let num_chunks = (file_size as usize + CHUNKSIZE + 1) / CHUNKSIZE;
-    // let file = Arc::new(file);
-    // let search_char = Arc::new(search_char);
-    // let acceptable_types = Arc::new(acceptable_types);
-    let (tx, rx) = mpsc::channel();
-
-    let workthread = thread::spawn(move || {
-        for i in 0..num_chunks {
-            // let mut file = Arc::clone(&file);
-            let tx = tx.clone();
-            // let search_char = Arc::clone(&search_char);
-            // let acceptable_types = Arc::clone(&acceptable_types);
-            let mut chunk = vec![0; CHUNKSIZE.min(file_size as usize - i * CHUNKSIZE)];
-
-            file.seek(SeekFrom::Start((i * CHUNKSIZE) as u64)).unwrap();
-            let _amount = file
-                .read(&mut chunk)
-                .expect(&format!("Could not open file {:?}", file));
-            let chunk = chunk.iter().map(|c| *c as char).collect();
-
-            tx.send(chunk_process(
-                &chunk,
-                &acceptable_types,
-                offset_back,
-                offset_front,
-                &search_char,
-            ))
-            .unwrap();
+use std::ops::Range;
+
+/// How the windows are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One CSV file per character, the characters of a window separated by commas
+    Csv,
+    /// One HDF5 file with a matrix of alphabet indices per character
+    Hdf5,
+}
+
+/// Collects the surrounding characters of every occurrence of the characters
+/// of an alphabet.
+///
+/// ```
+/// use character_gather::{Source, WindowExtractor};
+///
+/// let extractor = WindowExtractor::new("abc ".chars().collect())
+///     .offset_back(1)
+///     .offset_front(1);
+/// let windows = extractor.windows('b', &[Source::Memory(b"abc cba".to_vec())])?;
+/// assert_eq!(windows, vec![vec!['a', 'b', 'c'], vec!['c', 'b', 'a']]);
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct WindowExtractor {
+    alphabet: Vec<char>,
+    offset_back: usize,
+    offset_front: usize,
+    reading: ReadOptions,
+    unknown: bool,
+    progress: Option<Progress>,
+}
+
+impl WindowExtractor {
+    /// An extractor taking 4 characters in both directions, decoding UTF-8
+    /// lossy, reading 16 KiB at once and using one thread per core.
+    pub fn new(alphabet: Vec<char>) -> WindowExtractor {
+        WindowExtractor {
+            alphabet,
+            offset_back: 4,
+            offset_front: 4,
+            reading: ReadOptions::new(),
+            unknown: false,
+            progress: None,
+        }
+    }
+
+    /// How many characters in front of the searched character belong to a window.
+    pub fn offset_back(mut self, offset_back: usize) -> WindowExtractor {
+        self.offset_back = offset_back;
+        self
+    }
+
+    /// How many characters behind the searched character belong to a window.
+    pub fn offset_front(mut self, offset_front: usize) -> WindowExtractor {
+        self.offset_front = offset_front;
+        self
+    }
+
+    /// How the sources are read, see [`ReadOptions`].
+    pub fn read_options(mut self, reading: ReadOptions) -> WindowExtractor {
+        self.reading = reading;
+        self
+    }
+
+    /// Keeps the windows with characters outside of the alphabet, instead of
+    /// leaving them out. Only characters of the alphabet get windows of their
+    /// own.
+    pub fn unknown(mut self, unknown: bool) -> WindowExtractor {
+        self.unknown = unknown;
+        self
+    }
+
+    /// Calls `report` with the number of chunks handed to the sink so far and
+    /// the number of all chunks after every chunk.
+    pub fn progress(
+        mut self,
+        report: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) -> WindowExtractor {
+        self.progress = Some(Progress::new(report));
+        self
+    }
+
+    /// Reads the sources once and hands the windows around every character of
+    /// the alphabet to `sink`, one call per chunk of the sources in the order
+    /// of the chunks. The batch holds one list of windows per character of
+    /// the alphabet. Windows with a character outside of the alphabet are left
+    /// out unless [`WindowExtractor::unknown`] is set. An error of the sink
+    /// stops the extraction.
+    ///
+    /// Every occurrence with `offset_back` characters in front and
+    /// `offset_front` characters behind it in the same source yields exactly
+    /// one window, the way the sources are split into chunks does not matter.
+    pub fn extract<F: FnMut(Vec<Vec<Vec<char>>>) -> Result<()>>(
+        &self,
+        sources: &[Source],
+        sink: F,
+    ) -> Result<()> {
+        validate_chunksize(
+            self.reading.chunksize,
+            self.offset_back,
+            self.offset_front,
+            self.reading.decoding,
+        )?;
+        self.extract_chunked(sources, self.reading.chunksize, sink)
+    }
+
+    fn extract_chunked<F: FnMut(Vec<Vec<Vec<char>>>) -> Result<()>>(
+        &self,
+        sources: &[Source],
+        chunksize: usize,
+        mut sink: F,
+    ) -> Result<()> {
+        let chunks = plan(sources, chunksize);
+        let alphabet = match self.unknown {
+            true => Alphabet::with_unknown(self.alphabet.clone()),
+            false => Alphabet::new(self.alphabet.clone()),
+        };
+
+        let pool = self.reading.pool()?;
+
+        // A few chunks per thread are extracted in parallel, then handed to
+        // the sink in order before the next batch is started, so the output
+        // does not depend on the number of threads.
+        let batch_size = pool.current_num_threads() * 4;
+        let mut done = 0;
+        for batch in chunks.chunks(batch_size) {
+            let extracted: Vec<Result<Vec<Vec<Vec<char>>>>> = pool.install(|| {
+                batch
+                    .par_iter()
+                    .map(|&(document, start, end)| {
+                        // Like when counting, the chunk reads the characters of its
+                        // neighbours needed for the windows around its own centres.
+                        let chunk = read_chunk(
+                            &sources[document],
+                            start,
+                            end,
+                            self.offset_back,
+                            self.offset_front,
+                            self.reading.decoding,
+                            &self.reading.preprocessing,
+                        )?;
+                        let indices = chunk.characters.indices(&alphabet);
+                        // The windows are copied out anyway, so text is decoded
+                        let decoded: Vec<char> = match &chunk.characters {
+                            Characters::Text(text) => text.chars().collect(),
+                            _ => Vec::new(),
+                        };
+                        let character = |i: usize| match &chunk.characters {
+                            Characters::Bytes(bytes) => bytes[i] as char,
+                            Characters::Text(_) => decoded[i],
+                            Characters::Decoded(characters) => characters[i],
+                        };
+                        Ok(chunk_process(
+                            &indices,
+                            chunk.centres,
+                            character,
+                            alphabet.len(),
+                            self.offset_back,
+                            self.offset_front,
+                        ))
+                    })
+                    .collect()
+            });
+            for data in extracted {
+                sink(data?)?;
+                done += 1;
+                Progress::report(&self.progress, done, chunks.len());
+            }
+        }
+        Ok(())
+    }
+
+    /// All windows around `search_char` in `sources`.
+    pub fn windows(&self, search_char: char, sources: &[Source]) -> Result<Vec<Vec<char>>> {
+        let mut windows = Vec::new();
+        let index = Alphabet::new(self.alphabet.clone()).index(search_char);
+        if index == OUTSIDE {
+            return Ok(windows);
+        }
+        self.extract(sources, |mut data| {
+            windows.append(&mut data[index as usize]);
+            Ok(())
+        })?;
+        Ok(windows)
+    }
+}
+
+/// Writes the windows around every character of the alphabet as CSV, the
+/// windows of the n-th character of the alphabet go into `result_files[n]`.
+/// Characters outside of the alphabet are written as `<UNK>`.
+pub fn gather_datasets(
+    extractor: &WindowExtractor,
+    sources: &[Source],
+    mut result_files: Vec<File>,
+) -> Result<()> {
+    let alphabet = Alphabet::new(extractor.alphabet.clone());
+    extractor.extract(sources, |data| {
+        for (result_file, windows) in result_files.iter_mut().zip(data) {
+            append_to_csv(result_file, windows, &alphabet)?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes the windows around every character of the alphabet into `file`.
+/// The windows around the n-th character of the alphabet are the dataset
+/// `windows_<n>` of the shape `(windows, offset_back + offset_front + 1)`,
+/// every character of a window is stored as its index in the alphabet, a
+/// character outside of it as the length of the alphabet.
+pub fn gather_hdf5(
+    extractor: &WindowExtractor,
+    sources: &[Source],
+    file: &hdf5::File,
+) -> Result<()> {
+    let window = extractor.offset_back + extractor.offset_front + 1;
+    let alphabet = Alphabet::with_unknown(extractor.alphabet.clone());
+
+    // (dataset, rows written so far), the datasets grow with every chunk
+    let mut datasets = Vec::new();
+    for (index, character) in extractor.alphabet.iter().enumerate() {
+        let dataset = file
+            .new_dataset::<u32>()
+            .shape((0.., window))
+            .chunk((1024, window))
+            .create(format!("windows_{index}").as_str())?;
+        metadata::write_alphabet(&dataset, &extractor.alphabet)?;
+        metadata::write_string(&dataset, "centre", &character.to_string())?;
+        metadata::attribute::<bool>(&dataset, "unknown")?.write_scalar(&extractor.unknown)?;
+        metadata::attribute::<u64>(&dataset, "offset_back")?
+            .write_scalar(&(extractor.offset_back as u64))?;
+        metadata::attribute::<u64>(&dataset, "offset_front")?
+            .write_scalar(&(extractor.offset_front as u64))?;
+        datasets.push((dataset, 0));
+    }
+
+    extractor.extract(sources, |data| {
+        for ((dataset, rows), windows) in datasets.iter_mut().zip(data) {
+            if windows.is_empty() {
+                continue;
+            }
+            let indices: Vec<u32> = windows
+                .iter()
+                .flat_map(|window| alphabet.index_chars(window))
+                .collect();
+            let windows = Array2::from_shape_vec((windows.len(), window), indices)
+                .map_err(|e| Error::Schema(format!("{}: {e}", dataset.name())))?;
+            let end = *rows + windows.nrows();
+            dataset.resize((end, window))?;
+            dataset.write_slice(&windows, s![*rows..end, ..])?;
+            *rows = end;
         }
-    });
-
-    let mut counter: f32 = 0.0;
-    let num_chunks = num_chunks as f32;
-    for received in rx {
-        append_to_csv(&mut result_file, received).expect("Could not write data to csv");
-        counter += 1.0;
-        print!(
-            "At {counter} out of {num_chunks} = {:.1}%\r",
-            (counter / num_chunks) * 100.0
-        );
-    }
-    workthread.join().unwrap();
+        Ok(())
+    })
 }
 
-fn append_to_csv(file: &mut File, data: Vec<Vec<char>>) -> std::io::Result<()> {
+fn append_to_csv(file: &mut File, data: Vec<Vec<char>>, alphabet: &Alphabet) -> Result<()> {
     // Convert Vec<Vec<char>> to a string where each inner Vec is a comma-separated line
     let mut content = String::new();
     for row in data {
         let line: String = row
             .iter()
-            .map(|&c| c.to_string()) // Convert each char to a String
+            .map(|&c| match alphabet.index(c) {
+                OUTSIDE => UNKNOWN.to_string(),
+                _ => c.to_string(), // Convert each char to a String
+            })
             .collect::<Vec<_>>()
             .join(","); // Join the Vec<String> with commas
         content.push_str(&line);
@@ -79,36 +286,206 @@ fn append_to_csv(file: &mut File, data: Vec<Vec<char>>) -> std::io::Result<()> {
     Ok(())
 }
 
+// `indices` holds the alphabet index of every character (or OUTSIDE, or the
+// unknown index `alphabet_len`), the window around a character of the
+// alphabet goes into the list of its index. Centres closer than the offsets to
+// the start or end of `indices` are at the border of their source and have no
+// complete window.
 fn chunk_process(
-    buffer: &Vec<char>,
-    acceptable: &Vec<char>,
-    offset_back: isize,
-    offset_front: isize,
-    search_char: &char,
-) -> Vec<Vec<char>> {
-    let mut data = Vec::new();
-
-    if buffer.len() > offset_back as usize + offset_front as usize {
-        for i in offset_back..buffer.len() as isize - offset_front {
-            if &buffer[i as usize] == search_char {
-                let context: Vec<char> = (-offset_back..=offset_front)
-                    .map(|offset| buffer[(i + offset) as usize])
-                    .collect();
-                if validate_vec(&context, acceptable) {
-                    data.push(context);
+    indices: &[u32],
+    centres: Range<usize>,
+    character: impl Fn(usize) -> char,
+    alphabet_len: usize,
+    offset_back: usize,
+    offset_front: usize,
+) -> Vec<Vec<Vec<char>>> {
+    let mut data = vec![Vec::new(); alphabet_len];
+
+    let first = centres.start.max(offset_back);
+    let last = centres.end.min(indices.len().saturating_sub(offset_front));
+    for i in first..last {
+        let index = indices[i] as usize;
+        if index >= alphabet_len {
+            continue;
+        }
+        let context = i - offset_back..=i + offset_front;
+        if !indices[context.clone()].contains(&OUTSIDE) {
+            data[index].push(context.map(&character).collect());
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{Decoding, Encoding, InvalidBytes};
+    use crate::testing::{random_text, random_text_from};
+    use std::io::Write;
+
+    // Every window of the whole decoded text, without any chunks
+    fn naive_windows(
+        buffer: &[char],
+        acceptable: &[char],
+        offset_back: usize,
+        offset_front: usize,
+        unknown: bool,
+    ) -> Vec<Vec<Vec<char>>> {
+        let mut data = vec![Vec::new(); acceptable.len()];
+        for (i, character) in buffer.iter().enumerate() {
+            if i < offset_back || i + offset_front >= buffer.len() {
+                continue;
+            }
+            if let Some(index) = acceptable.iter().position(|c| c == character) {
+                let window = buffer[i - offset_back..=i + offset_front].to_vec();
+                if unknown || window.iter().all(|c| acceptable.contains(c)) {
+                    data[index].push(window);
                 }
             }
         }
+        data
     }
 
-    return data;
-}
+    #[test]
+    fn chunked_windows_match_whole_file() {
+        let acceptable_types: Vec<char> = "abcdefgh .äßд語😀\u{fffd}".chars().collect();
+        let text = random_text(5_000, 0xc4a2);
+        let path =
+            std::env::temp_dir().join(format!("char_dataset_chunks_{}.txt", std::process::id()));
+        File::create(&path).unwrap().write_all(&text).unwrap();
 
-fn validate_vec(vector: &Vec<char>, acceptable: &Vec<char>) -> bool {
-    for character in vector {
-        if !acceptable.contains(character) {
-            return false;
+        let decodings = [
+            Decoding::new(Encoding::Latin1, InvalidBytes::Strict),
+            Decoding::new(Encoding::Utf8, InvalidBytes::Lossy),
+            Decoding::new(Encoding::Utf8, InvalidBytes::Skip),
+        ];
+        for ((offset_back, offset_front), decoding) in [(3, 3), (0, 5), (7, 1)]
+            .into_iter()
+            .flat_map(|offsets| decodings.map(|decoding| (offsets, decoding)))
+        {
+            let buffer = decoding.decode(&text, 0).unwrap();
+            for unknown in [false, true] {
+                let expected = naive_windows(
+                    &buffer,
+                    &acceptable_types,
+                    offset_back,
+                    offset_front,
+                    unknown,
+                );
+                let extractor = WindowExtractor::new(acceptable_types.clone())
+                    .offset_back(offset_back)
+                    .offset_front(offset_front)
+                    .unknown(unknown);
+
+                for (chunksize, threads, mmap) in [(1, 1, false), (7, 4, true), (64, 2, false)] {
+                    let sources = [Source::new(File::open(&path).unwrap(), mmap).unwrap()];
+                    let mut data = vec![Vec::new(); acceptable_types.len()];
+                    extractor
+                        .clone()
+                        .read_options(ReadOptions::new().threads(threads).decoding(decoding))
+                        .extract_chunked(&sources, chunksize, |batch| {
+                            for (windows, mut found) in data.iter_mut().zip(batch) {
+                                windows.append(&mut found);
+                            }
+                            Ok(())
+                        })
+                        .unwrap();
+                    assert_eq!(data, expected, "chunksize {chunksize} {decoding:?}");
+                }
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn csv_matches_windows_of_every_character() {
+        let acceptable_types: Vec<char> = "abcdefgh .äд😀".chars().collect();
+        let sources = [
+            Source::Memory(random_text(4_000, 0x5a1)),
+            Source::Memory(random_text(300, 0x5a2)),
+        ];
+        let directory = tempfile::tempdir().unwrap();
+        let alphabet = Alphabet::new(acceptable_types.clone());
+
+        for unknown in [false, true] {
+            let extractor = WindowExtractor::new(acceptable_types.clone())
+                .offset_back(2)
+                .offset_front(3)
+                .read_options(ReadOptions::new().threads(3).chunksize(64))
+                .unknown(unknown);
+            let paths: Vec<_> = (0..acceptable_types.len())
+                .map(|index| directory.path().join(format!("{unknown}_{index}.csv")))
+                .collect();
+            let files = paths
+                .iter()
+                .map(|path| File::create(path).unwrap())
+                .collect();
+            gather_datasets(&extractor, &sources, files).unwrap();
+
+            for (&character, path) in acceptable_types.iter().zip(&paths) {
+                let windows = extractor.windows(character, &sources).unwrap();
+                assert!(!windows.is_empty(), "{character:?}");
+                let expected: String = windows
+                    .iter()
+                    .map(|window| {
+                        let characters: Vec<String> = window
+                            .iter()
+                            .map(|&c| match alphabet.index(c) {
+                                OUTSIDE => UNKNOWN.to_string(),
+                                _ => c.to_string(),
+                            })
+                            .collect();
+                        characters.join(",") + ",\n"
+                    })
+                    .collect();
+                assert_eq!(std::fs::read_to_string(path).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn hdf5_holds_the_windows_as_indices() {
+        use hdf5::types::VarLenUnicode;
+
+        // , and line breaks can not be told apart from the CSV layout
+        let acceptable_types = vec!['a', ',', '\n'];
+        let sources = [Source::Memory(random_text_from(2_000, 0x4df, "a,\nxy"))];
+        let directory = tempfile::tempdir().unwrap();
+        for unknown in [false, true] {
+            let extractor = WindowExtractor::new(acceptable_types.clone())
+                .offset_back(2)
+                .offset_front(1)
+                .read_options(ReadOptions::new().chunksize(16))
+                .unknown(unknown);
+            let file = hdf5::File::create(directory.path().join(format!("{unknown}.h5"))).unwrap();
+            gather_hdf5(&extractor, &sources, &file).unwrap();
+
+            let alphabet = Alphabet::with_unknown(acceptable_types.clone());
+            for (index, &character) in acceptable_types.iter().enumerate() {
+                let dataset = file.dataset(&format!("windows_{index}")).unwrap();
+                let windows = extractor.windows(character, &sources).unwrap();
+                assert_eq!(dataset.shape(), vec![windows.len(), 4]);
+                let expected: Vec<u32> = windows
+                    .iter()
+                    .flat_map(|window| alphabet.index_chars(window))
+                    .collect();
+                let stored = dataset.read_2d::<u32>().unwrap();
+                assert_eq!(stored.iter().copied().collect::<Vec<u32>>(), expected);
+                // Characters outside of the alphabet are stored as its length
+                assert_eq!(stored.iter().any(|&index| index == 3), unknown);
+                assert!(stored
+                    .column(2)
+                    .iter()
+                    .all(|&centre| centre == index as u32));
+
+                assert_eq!(metadata::read_alphabet(&dataset).unwrap(), acceptable_types);
+                let centre: VarLenUnicode = dataset.attr("centre").unwrap().read_scalar().unwrap();
+                assert_eq!(centre.as_str(), character.to_string());
+                assert_eq!(metadata::read_unknown(&dataset).unwrap(), unknown);
+                assert_eq!(metadata::read_offset(&dataset, "offset_back").unwrap(), 2);
+                assert_eq!(metadata::read_offset(&dataset, "offset_front").unwrap(), 1);
+            }
         }
     }
-    return true;
 }
@@ -0,0 +1,363 @@
+use crate::alphabet::Alphabet;
+use crate::decode::{Decoding, Encoding, InvalidBytes};
+use crate::error::{Error, Result};
+use crate::preprocess::Preprocessing;
+use crate::source::Source;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Default number of bytes read at once
+pub const CHUNKSIZE: usize = 4096 * 4;
+
+/// How the sources are read, shared by [`crate::CooccurrenceCounter`],
+/// [`crate::WindowExtractor`] and [`crate::FrequencyCounter`].
+///
+/// ```
+/// use character_gather::{Decoding, Encoding, InvalidBytes, ReadOptions};
+///
+/// let options = ReadOptions::new()
+///     .threads(2)
+///     .decoding(Decoding::new(Encoding::Latin1, InvalidBytes::Strict));
+/// assert_eq!(options.threads, 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReadOptions {
+    pub threads: usize,
+    pub chunksize: usize,
+    pub decoding: Decoding,
+    pub preprocessing: Preprocessing,
+}
+
+impl Default for ReadOptions {
+    fn default() -> ReadOptions {
+        ReadOptions::new()
+    }
+}
+
+impl ReadOptions {
+    /// Decoding UTF-8 lossy, reading 16 KiB at once and using one thread per
+    /// core.
+    pub fn new() -> ReadOptions {
+        ReadOptions {
+            threads: 0,
+            chunksize: CHUNKSIZE,
+            decoding: Decoding::new(Encoding::Utf8, InvalidBytes::Lossy),
+            preprocessing: Preprocessing::default(),
+        }
+    }
+
+    /// Size of the thread pool, 0 uses one thread per core.
+    pub fn threads(mut self, threads: usize) -> ReadOptions {
+        self.threads = threads;
+        self
+    }
+
+    /// How many bytes are read at once, at least one window.
+    pub fn chunksize(mut self, chunksize: usize) -> ReadOptions {
+        self.chunksize = chunksize;
+        self
+    }
+
+    /// How the bytes of the sources are turned into characters.
+    pub fn decoding(mut self, decoding: Decoding) -> ReadOptions {
+        self.decoding = decoding;
+        self
+    }
+
+    /// What is done to the characters before they are looked at.
+    pub fn preprocessing(mut self, preprocessing: Preprocessing) -> ReadOptions {
+        self.preprocessing = preprocessing;
+        self
+    }
+
+    pub(crate) fn pool(&self) -> Result<rayon::ThreadPool> {
+        Ok(rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()?)
+    }
+}
+
+/// Splits every source into chunks of `chunksize` bytes, as
+/// `(source, start, end)`. Chunks never span two sources, every source is a
+/// document of its own.
+pub fn plan(sources: &[Source], chunksize: usize) -> Vec<(usize, usize, usize)> {
+    sources
+        .iter()
+        .enumerate()
+        .flat_map(|(document, source)| {
+            (0..source.len().div_ceil(chunksize)).map(move |i| {
+                let start = i * chunksize;
+                (document, start, (start + chunksize).min(source.len()))
+            })
+        })
+        .collect()
+}
+
+/// Chunks smaller than a window would read more bytes of their neighbours
+/// than of their own.
+pub fn validate_chunksize(
+    chunksize: usize,
+    offset_back: usize,
+    offset_front: usize,
+    decoding: Decoding,
+) -> Result<()> {
+    let window = (offset_back + offset_front + 1) * decoding.max_width();
+    if chunksize < window {
+        return Err(Error::Validation(format!(
+            "The chunk size of {chunksize} bytes is smaller than a window of up to {window} bytes"
+        )));
+    }
+    Ok(())
+}
+
+/// Reports how many chunks are done out of how many, possibly from several
+/// threads at once.
+#[derive(Clone)]
+pub struct Progress(Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl Progress {
+    pub fn new(report: impl Fn(usize, usize) + Send + Sync + 'static) -> Progress {
+        Progress(Arc::new(report))
+    }
+
+    pub fn report(progress: &Option<Progress>, done: usize, total: usize) {
+        if let Some(Progress(report)) = progress {
+            report(done, total);
+        }
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Progress")
+    }
+}
+
+pub enum Characters<'a> {
+    // Latin-1 input needs no decoding, every byte already is a character
+    Bytes(Cow<'a, [u8]>),
+    // Valid UTF-8 is only checked, not decoded
+    Text(Cow<'a, str>),
+    Decoded(Vec<char>),
+}
+
+impl Characters<'_> {
+    /// The index of every character in `alphabet`.
+    pub fn indices(&self, alphabet: &Alphabet) -> Vec<u32> {
+        match self {
+            Characters::Bytes(bytes) => alphabet.index_bytes(bytes),
+            Characters::Text(text) => alphabet.index_str(text),
+            Characters::Decoded(characters) => alphabet.index_chars(characters),
+        }
+    }
+}
+
+pub struct Chunk<'a> {
+    pub characters: Characters<'a>,
+    pub centres: Range<usize>,
+}
+
+/// Reads and decodes the bytes `start..end` of `source` together with up to
+/// `offset_back` characters in front and `offset_front` characters behind it.
+///
+/// Both borders are moved onto character boundaries, so every character of the
+/// file ends up in the `centres` of exactly one chunk no matter how the file is
+/// split up. The same holds for the characters after `preprocessing`.
+pub fn read_chunk<'a>(
+    source: &'a Source,
+    start: usize,
+    end: usize,
+    offset_back: usize,
+    offset_front: usize,
+    decoding: Decoding,
+    preprocessing: &Preprocessing,
+) -> Result<Chunk<'a>> {
+    if preprocessing.is_identity() {
+        return read_decoded(source, start, end, offset_back, offset_front, decoding);
+    }
+    if preprocessing.only_maps() {
+        let chunk = read_decoded(source, start, end, offset_back, offset_front, decoding)?;
+        let mapping = &preprocessing.mapping;
+        let characters = match chunk.characters {
+            Characters::Bytes(bytes) => bytes.iter().map(|&c| mapping.get(c as char)).collect(),
+            Characters::Text(text) => text.chars().map(|c| mapping.get(c)).collect(),
+            Characters::Decoded(characters) => {
+                characters.into_iter().map(|c| mapping.get(c)).collect()
+            }
+        };
+        return Ok(Chunk {
+            characters: Characters::Decoded(characters),
+            centres: chunk.centres,
+        });
+    }
+
+    // Preprocessing can merge, split and drop characters, so it is not known
+    // in advance how many characters around the chunk are needed.
+    let mut margin = (offset_back.max(offset_front) + 1).max(4);
+    loop {
+        let chunk = read_decoded(source, start, end, margin, margin, decoding)?;
+        let characters = match chunk.characters {
+            Characters::Bytes(bytes) => bytes.iter().map(|&c| c as char).collect(),
+            Characters::Text(text) => text.chars().collect(),
+            Characters::Decoded(characters) => characters,
+        };
+        let at_start = chunk.centres.start < margin;
+        let at_end = characters.len() - chunk.centres.end < margin;
+        if let Some((characters, centres)) = preprocessing.apply(
+            &characters,
+            chunk.centres,
+            at_start,
+            at_end,
+            offset_back,
+            offset_front,
+        ) {
+            return Ok(Chunk {
+                characters: Characters::Decoded(characters),
+                centres,
+            });
+        }
+        margin *= 2;
+    }
+}
+
+fn read_decoded(
+    source: &Source,
+    start: usize,
+    end: usize,
+    offset_back: usize,
+    offset_front: usize,
+    decoding: Decoding,
+) -> Result<Chunk<'_>> {
+    let file_size = source.len();
+    if decoding.encoding == Encoding::Latin1 {
+        let read_start = start.saturating_sub(offset_back);
+        let read_end = (end + offset_front).min(file_size);
+        let bytes = source.read(read_start..read_end)?;
+        return Ok(Chunk {
+            characters: Characters::Bytes(bytes),
+            centres: start - read_start..end - read_start,
+        });
+    }
+
+    let width = decoding.max_width();
+    let mut margin_back = offset_back * width;
+    let mut margin_front = offset_front * width;
+
+    loop {
+        let read_start = start.saturating_sub(margin_back + width - 1);
+        let read_end = (end + margin_front + 1).min(file_size);
+        let bytes = source.read(read_start..read_end)?;
+
+        let chunk_start = decoding.boundary(&bytes, start - read_start);
+        let chunk_end = decoding.boundary(&bytes, end - read_start);
+        let halo_start = match read_start {
+            0 => 0,
+            _ => decoding.boundary(&bytes, width - 1).min(chunk_start),
+        };
+        let halo_end = match read_end == file_size {
+            true => bytes.len(),
+            false => decoding.boundary(&bytes, bytes.len() - 1).max(chunk_end),
+        };
+
+        if let Ok(text) = std::str::from_utf8(&bytes[halo_start..halo_end]) {
+            let (back, rest) = text.split_at(chunk_start - halo_start);
+            let (centre, front) = rest.split_at(chunk_end - chunk_start);
+            let back_len = back.chars().count();
+            if back_len < offset_back && read_start > 0 {
+                margin_back = (margin_back * 2).max(1);
+                continue;
+            }
+            if front.chars().count() < offset_front && read_end < file_size {
+                margin_front = (margin_front * 2).max(1);
+                continue;
+            }
+            let back_start = match offset_back {
+                0 => back.len(),
+                _ => back
+                    .char_indices()
+                    .rev()
+                    .nth(offset_back - 1)
+                    .map_or(0, |(i, _)| i),
+            };
+            let front_end = front
+                .char_indices()
+                .nth(offset_front)
+                .map_or(front.len(), |(i, _)| i);
+            let centre_start = back_len.min(offset_back);
+            let centres = centre_start..centre_start + centre.chars().count();
+
+            let range = halo_start + back_start..chunk_end + front_end;
+            // Safe as the whole halo was just checked to be valid UTF-8, and
+            // the range starts and ends on character boundaries
+            let text = match bytes {
+                Cow::Borrowed(bytes) => {
+                    Cow::Borrowed(unsafe { std::str::from_utf8_unchecked(&bytes[range]) })
+                }
+                Cow::Owned(mut bytes) => {
+                    bytes.truncate(range.end);
+                    bytes.drain(..range.start);
+                    Cow::Owned(unsafe { String::from_utf8_unchecked(bytes) })
+                }
+            };
+            return Ok(Chunk {
+                characters: Characters::Text(text),
+                centres,
+            });
+        }
+
+        let back = decoding.decode(&bytes[halo_start..chunk_start], read_start + halo_start)?;
+        let front = decoding.decode(&bytes[chunk_end..halo_end], read_start + chunk_end)?;
+        if back.len() < offset_back && read_start > 0 {
+            margin_back = (margin_back * 2).max(1);
+            continue;
+        }
+        if front.len() < offset_front && read_end < file_size {
+            margin_front = (margin_front * 2).max(1);
+            continue;
+        }
+
+        let mut characters = back[back.len().saturating_sub(offset_back)..].to_vec();
+        let centre_start = characters.len();
+        characters
+            .extend(decoding.decode(&bytes[chunk_start..chunk_end], read_start + chunk_start)?);
+        let centre_end = characters.len();
+        characters.extend(&front[..front.len().min(offset_front)]);
+
+        return Ok(Chunk {
+            characters: Characters::Decoded(characters),
+            centres: centre_start..centre_end,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::InvalidBytes;
+
+    #[test]
+    fn valid_utf8_is_borrowed() {
+        let source = Source::Memory("ab\u{e4}d\u{8a9e}f\u{1f600}h".as_bytes().to_vec());
+        let decoding = Decoding::new(Encoding::Utf8, InvalidBytes::Strict);
+        // The chunk starts in the middle of \u{e4} and ends in \u{8a9e}
+        let chunk = read_chunk(&source, 3, 6, 2, 2, decoding, &Preprocessing::default()).unwrap();
+        match chunk.characters {
+            Characters::Text(Cow::Borrowed(text)) => assert_eq!(text, "ab\u{e4}d\u{8a9e}f"),
+            _ => panic!("Valid UTF-8 was copied"),
+        }
+        assert_eq!(chunk.centres, 2..4);
+
+        let source = Source::Memory(b"ab\xffcd".to_vec());
+        let decoding = Decoding::new(Encoding::Utf8, InvalidBytes::Lossy);
+        let chunk = read_chunk(&source, 1, 4, 1, 1, decoding, &Preprocessing::default()).unwrap();
+        match chunk.characters {
+            Characters::Decoded(characters) => {
+                assert_eq!(characters, vec!['a', 'b', '\u{fffd}', 'c', 'd'])
+            }
+            _ => panic!("Invalid UTF-8 was not decoded"),
+        }
+        assert_eq!(chunk.centres, 1..4);
+    }
+}
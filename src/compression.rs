@@ -0,0 +1,87 @@
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Number of bytes at the start of the input [`Compression::detect`] looks at
+pub const MAGIC_LEN: usize = 10;
+
+/// Compression formats that are decompressed while reading the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// The magic bytes win over the file extension, so a misnamed file is still
+    /// read correctly. [`MAGIC_LEN`] bytes of `header` are enough for every format.
+    pub fn detect(header: &[u8], path: Option<&Path>) -> Option<Compression> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            return Some(Compression::Gzip);
+        }
+        if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return Some(Compression::Zstd);
+        }
+        // "BZh", the block size from 1 to 9 and the magic of the first block,
+        // or of the end of the stream if nothing was compressed. "BZh" alone is
+        // just as likely the start of a text.
+        if let [b'B', b'Z', b'h', b'1'..=b'9', block @ ..] = header {
+            if block.starts_with(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59])
+                || block.starts_with(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90])
+            {
+                return Some(Compression::Bzip2);
+            }
+        }
+        if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            return Some(Compression::Xz);
+        }
+        let extension = path?.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            "xz" => Some(Compression::Xz),
+            _ => None,
+        }
+    }
+
+    pub fn decoder<'a, R: Read + 'a>(&self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        let reader = io::BufReader::new(reader);
+        Ok(match self {
+            Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+            Compression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+            Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bzip2_needs_its_block_magic() {
+        let mut compressed = Vec::new();
+        bzip2::read::BzEncoder::new(&b"text"[..], bzip2::Compression::fast())
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(
+            Compression::detect(&compressed, None),
+            Some(Compression::Bzip2)
+        );
+        let mut empty = Vec::new();
+        bzip2::read::BzEncoder::new(&b""[..], bzip2::Compression::best())
+            .read_to_end(&mut empty)
+            .unwrap();
+        assert_eq!(Compression::detect(&empty, None), Some(Compression::Bzip2));
+
+        assert_eq!(Compression::detect(b"BZh9 is a text", None), None);
+        assert_eq!(Compression::detect(b"BZh", None), None);
+        assert_eq!(
+            Compression::detect(b"BZh", Some(Path::new("text.bz2"))),
+            Some(Compression::Bzip2)
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::error::{Error, Result};
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// Decode the input as UTF-8 into Unicode scalar values
+    Utf8,
+    /// Every byte is one character (ISO-8859-1), the behaviour of older versions
+    Latin1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InvalidBytes {
+    /// Abort on the first invalid UTF-8 sequence
+    Strict,
+    /// Replace every invalid sequence with U+FFFD
+    Lossy,
+    /// Drop invalid sequences as if they were not part of the text
+    Skip,
+}
+
+/// How bytes are turned into characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decoding {
+    pub encoding: Encoding,
+    pub invalid: InvalidBytes,
+}
+
+impl Decoding {
+    pub fn new(encoding: Encoding, invalid: InvalidBytes) -> Decoding {
+        Decoding { encoding, invalid }
+    }
+
+    /// Maximum amount of bytes a single character can take up
+    pub(crate) fn max_width(&self) -> usize {
+        match self.encoding {
+            Encoding::Utf8 => 4,
+            Encoding::Latin1 => 1,
+        }
+    }
+
+    /// Moves `position` back onto the start of the character it is part of.
+    ///
+    /// Only the three bytes in front of `position` are looked at, so the result
+    /// is the same no matter where the surrounding buffer was cut. A non
+    /// continuation byte always starts a new character, and after three
+    /// continuation bytes any further continuation byte is an invalid sequence
+    /// of its own.
+    pub(crate) fn boundary(&self, bytes: &[u8], position: usize) -> usize {
+        if self.encoding == Encoding::Latin1 || position >= bytes.len() {
+            return position;
+        }
+        for candidate in (position.saturating_sub(3)..=position).rev() {
+            if !is_continuation(bytes[candidate]) {
+                return candidate;
+            }
+        }
+        position
+    }
+
+    /// Decodes `bytes` which have to start and end on a character boundary.
+    /// `position` is the offset of `bytes` in the input and only used for
+    /// the error of invalid bytes.
+    pub fn decode(&self, bytes: &[u8], position: usize) -> Result<Vec<char>> {
+        match self.encoding {
+            Encoding::Latin1 => Ok(bytes.iter().map(|c| *c as char).collect()),
+            Encoding::Utf8 => {
+                let mut characters = Vec::with_capacity(bytes.len());
+                let mut rest = bytes;
+                loop {
+                    match std::str::from_utf8(rest) {
+                        Ok(valid) => {
+                            characters.extend(valid.chars());
+                            break;
+                        }
+                        Err(e) => {
+                            let valid_up_to = e.valid_up_to();
+                            let valid = std::str::from_utf8(&rest[..valid_up_to]).unwrap();
+                            characters.extend(valid.chars());
+                            match self.invalid {
+                                InvalidBytes::Strict => {
+                                    return Err(Error::Decode {
+                                        position: position
+                                            + (bytes.len() - rest.len())
+                                            + valid_up_to,
+                                    })
+                                }
+                                InvalidBytes::Lossy => characters.push(char::REPLACEMENT_CHARACTER),
+                                InvalidBytes::Skip => {}
+                            }
+                            match e.error_len() {
+                                Some(len) => rest = &rest[valid_up_to + len..],
+                                None => break,
+                            }
+                        }
+                    }
+                }
+                Ok(characters)
+            }
+        }
+    }
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
@@ -0,0 +1,181 @@
+use crate::chunk::{plan, read_chunk, validate_chunksize, Characters, ReadOptions};
+use crate::error::{Error, Result};
+use crate::source::Source;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Counts how often every character is found in a corpus, to choose the
+/// alphabet from.
+///
+/// ```
+/// use character_gather::{FrequencyCounter, Selection, Source};
+///
+/// let frequencies = FrequencyCounter::new().count(&[Source::Memory(b"abacab".to_vec())])?;
+/// assert_eq!(frequencies, vec![('a', 3), ('b', 2), ('c', 1)]);
+/// assert_eq!(Selection::Top(2).select(&frequencies), vec!['a', 'b']);
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct FrequencyCounter {
+    reading: ReadOptions,
+}
+
+impl Default for FrequencyCounter {
+    fn default() -> FrequencyCounter {
+        FrequencyCounter::new()
+    }
+}
+
+impl FrequencyCounter {
+    /// A counter decoding UTF-8 lossy, reading 16 KiB at once and using one
+    /// thread per core.
+    pub fn new() -> FrequencyCounter {
+        FrequencyCounter {
+            reading: ReadOptions::new(),
+        }
+    }
+
+    /// How the sources are read, see [`ReadOptions`].
+    pub fn read_options(mut self, reading: ReadOptions) -> FrequencyCounter {
+        self.reading = reading;
+        self
+    }
+
+    /// Every character found in `sources` together with how often it was
+    /// found, the most frequent character first.
+    pub fn count(&self, sources: &[Source]) -> Result<Vec<(char, u64)>> {
+        validate_chunksize(self.reading.chunksize, 0, 0, self.reading.decoding)?;
+        let chunks = plan(sources, self.reading.chunksize);
+
+        let pool = self.reading.pool()?;
+
+        let counts = pool.install(|| {
+            chunks
+                .par_iter()
+                .try_fold(
+                    HashMap::new,
+                    |mut counts: HashMap<char, u64>, &(document, start, end)| -> Result<_> {
+                        let chunk = read_chunk(
+                            &sources[document],
+                            start,
+                            end,
+                            0,
+                            0,
+                            self.reading.decoding,
+                            &self.reading.preprocessing,
+                        )?;
+                        match &chunk.characters {
+                            Characters::Bytes(bytes) => {
+                                // Latin-1 has only 256 characters, a table is
+                                // much cheaper than hashing every byte
+                                let mut table = [0u64; 256];
+                                for &byte in &bytes[chunk.centres] {
+                                    table[byte as usize] += 1;
+                                }
+                                for (byte, &count) in table.iter().enumerate() {
+                                    if count > 0 {
+                                        *counts.entry(byte as u8 as char).or_insert(0) += count;
+                                    }
+                                }
+                            }
+                            Characters::Text(text) => {
+                                let centres = text
+                                    .chars()
+                                    .skip(chunk.centres.start)
+                                    .take(chunk.centres.len());
+                                for character in centres {
+                                    *counts.entry(character).or_insert(0) += 1;
+                                }
+                            }
+                            Characters::Decoded(characters) => {
+                                for &character in &characters[chunk.centres] {
+                                    *counts.entry(character).or_insert(0) += 1;
+                                }
+                            }
+                        }
+                        Ok(counts)
+                    },
+                )
+                .try_reduce(HashMap::new, |mut sum, counts| {
+                    for (character, count) in counts {
+                        *sum.entry(character).or_insert(0) += count;
+                    }
+                    Ok(sum)
+                })
+        })?;
+
+        let mut frequencies: Vec<(char, u64)> = counts.into_iter().collect();
+        frequencies.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        Ok(frequencies)
+    }
+}
+
+/// How the alphabet is chosen out of the character frequencies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    /// Every character found
+    All,
+    /// The n most frequent characters
+    Top(usize),
+    /// Every character found at least this often
+    MinCount(u64),
+    /// The most frequent characters that together make up at least this
+    /// percentage of the corpus
+    Coverage(f64),
+}
+
+impl Selection {
+    /// Chooses the alphabet out of `frequencies`, which have to be sorted like
+    /// [`FrequencyCounter::count`] returns them. The alphabet is sorted by
+    /// code point.
+    pub fn select(&self, frequencies: &[(char, u64)]) -> Vec<char> {
+        let total: u64 = frequencies.iter().map(|(_, count)| count).sum();
+        let mut alphabet: Vec<char> = match *self {
+            Selection::All => frequencies.iter().map(|(c, _)| *c).collect(),
+            Selection::Top(n) => frequencies.iter().take(n).map(|(c, _)| *c).collect(),
+            Selection::MinCount(min) => frequencies
+                .iter()
+                .take_while(|(_, count)| *count >= min)
+                .map(|(c, _)| *c)
+                .collect(),
+            Selection::Coverage(percent) => {
+                let mut covered = 0;
+                frequencies
+                    .iter()
+                    .take_while(|(_, count)| {
+                        let needed = (covered as f64) < total as f64 * percent / 100.0;
+                        covered += count;
+                        needed
+                    })
+                    .map(|(c, _)| *c)
+                    .collect()
+            }
+        };
+        alphabet.sort_unstable();
+        alphabet
+    }
+
+    /// Checks that the coverage is a percentage.
+    pub fn validate(&self) -> Result<()> {
+        if let Selection::Coverage(percent) = *self {
+            if !(percent > 0.0 && percent <= 100.0) {
+                return Err(Error::Validation(format!(
+                    "The coverage has to be a percentage above 0, got {percent}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::All => write!(f, "all"),
+            Selection::Top(n) => write!(f, "top {n}"),
+            Selection::MinCount(min) => write!(f, "min count {min}"),
+            Selection::Coverage(percent) => write!(f, "coverage {percent}%"),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong in this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the input or writing the output failed
+    Io(io::Error),
+    /// The HDF5 library reported an error
+    Hdf5(hdf5::Error),
+    /// An HDF5 file is missing a dataset or attribute, or it has the wrong shape
+    Schema(String),
+    /// Invalid arguments, like an unknown normalizer or a negative offset
+    Validation(String),
+    /// The input is not valid UTF-8 and invalid bytes are not allowed
+    Decode { position: usize },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The process exit code for this kind of error, so scripts can tell the
+    /// failure classes apart. 1 is left for panics and 2 for clap usage errors.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io(_) => 3,
+            Error::Hdf5(_) => 4,
+            Error::Schema(_) => 5,
+            Error::Validation(_) => 6,
+            Error::Decode { .. } => 7,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Hdf5(e) => write!(f, "HDF5 error: {e}"),
+            Error::Schema(message) => write!(f, "Unexpected file layout: {message}"),
+            Error::Validation(message) => write!(f, "Invalid argument: {message}"),
+            Error::Decode { position } => write!(f, "Invalid UTF-8 at byte {position}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Hdf5(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<hdf5::Error> for Error {
+    fn from(e: hdf5::Error) -> Error {
+        Error::Hdf5(e)
+    }
+}
+
+impl From<rayon::ThreadPoolBuildError> for Error {
+    fn from(e: rayon::ThreadPoolBuildError) -> Error {
+        Error::Io(io::Error::other(e))
+    }
+}
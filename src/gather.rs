@@ -1,129 +1,433 @@
-use ndarray::{Array3, ArrayBase, Dim, OwnedRepr};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, Read, Seek, SeekFrom};
-use std::sync::{mpsc, Arc};
-use std::thread;
-
-const CHUNKSIZE: usize = 4096 * 4;
-
-pub fn gather_characters(
-    acceptable_types: Vec<char>,
-    offset_back: isize,
-    offset_front: isize,
-    file: File,
-    threads: usize,
-) -> Array3<u64> {
-    let file_size = file.metadata().expect("Could not read file metadata").len();
-    let num_chunks = (file_size as usize + CHUNKSIZE + 1) / CHUNKSIZE;
-    let file = Arc::new(file);
-
-    let index_map: HashMap<char, usize> = acceptable_types
-        .iter()
-        .enumerate()
-        .map(|(index, &ch)| (ch, index))
-        .collect();
-    let index_map = Arc::new(index_map);
-    let acceptable_types = Arc::new(acceptable_types);
-    let (tx, rx) = mpsc::channel();
-
-    let mut final_sum = Array3::<u64>::zeros((
-        acceptable_types.len(),
-        acceptable_types.len(),
-        offset_back as usize + offset_front as usize + 1,
-    ));
-
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(threads)
-        .build()
-        .expect("Could not build thread pool builder");
-
-    let thread_spawner = thread::spawn(move || {
-        pool.scope(|s| {
-            for i in 0..num_chunks {
-                let mut file = Arc::clone(&file);
-                let tx = tx.clone();
-
-                let index_map = Arc::clone(&index_map);
-                let acceptable_types = Arc::clone(&acceptable_types);
-                s.spawn(move |_| {
-                    // print!("At {i} out of {num_chunks}\r");
-                    let mut chunk = vec![0; CHUNKSIZE.min(file_size as usize - i * CHUNKSIZE)];
-
-                    file.seek(SeekFrom::Start((i * CHUNKSIZE) as u64)).unwrap();
-                    let _amount = file.read(&mut chunk).unwrap();
-                    let chunk = chunk.iter().map(|c| *c as char).collect();
-
-                    tx.send(line_process(
-                        &chunk,
-                        &acceptable_types,
-                        offset_back,
-                        offset_front,
-                        &index_map,
-                    ))
-                    .unwrap();
-                });
-            }
-        });
-    });
-
-    let mut counter: f32 = 0.0;
-    let num_chunks = num_chunks as f32;
-    for received in rx {
-        final_sum += &received;
-        counter += 1.0;
-        print!(
-            "At {counter} out of {num_chunks} = {:.1}%\r",
-            (counter / num_chunks) * 100.0
-        );
+use crate::alphabet::{Alphabet, OUTSIDE};
+use crate::chunk::{plan, read_chunk, validate_chunksize, Progress, ReadOptions};
+use crate::error::Result;
+use crate::source::Source;
+use ndarray::Array3;
+use rayon::prelude::*;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts how often every character of an alphabet is found at every
+/// distance in front of and behind every other character of the alphabet.
+///
+/// ```
+/// use character_gather::{CooccurrenceCounter, Source};
+///
+/// let counter = CooccurrenceCounter::new("abc ".chars().collect())
+///     .offset_back(2)
+///     .offset_front(2);
+/// let counts = counter.count(&[Source::Memory(b"abc cab".to_vec())])?;
+/// // counts[(base, found, distance + offset_back)]
+/// assert_eq!(counts.shape(), &[4, 4, 5]);
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct CooccurrenceCounter {
+    alphabet: Vec<char>,
+    offset_back: usize,
+    offset_front: usize,
+    reading: ReadOptions,
+    unknown: bool,
+    progress: Option<Progress>,
+}
+
+impl CooccurrenceCounter {
+    /// A counter looking 4 characters in both directions, decoding UTF-8
+    /// lossy, reading 16 KiB at once and using one thread per core.
+    pub fn new(alphabet: Vec<char>) -> CooccurrenceCounter {
+        CooccurrenceCounter {
+            alphabet,
+            offset_back: 4,
+            offset_front: 4,
+            reading: ReadOptions::new(),
+            unknown: false,
+            progress: None,
+        }
+    }
+
+    /// How many characters in front of each character are looked at.
+    pub fn offset_back(mut self, offset_back: usize) -> CooccurrenceCounter {
+        self.offset_back = offset_back;
+        self
+    }
+
+    /// How many characters behind each character are looked at.
+    pub fn offset_front(mut self, offset_front: usize) -> CooccurrenceCounter {
+        self.offset_front = offset_front;
+        self
+    }
+
+    /// How the sources are read, see [`ReadOptions`].
+    pub fn read_options(mut self, reading: ReadOptions) -> CooccurrenceCounter {
+        self.reading = reading;
+        self
+    }
+
+    /// Counts every character outside of the alphabet as one extra character
+    /// after the last of the alphabet instead of leaving it out.
+    pub fn unknown(mut self, unknown: bool) -> CooccurrenceCounter {
+        self.unknown = unknown;
+        self
+    }
+
+    /// Calls `report` with the number of chunks counted so far and the number
+    /// of all chunks whenever a chunk is done, from the thread counting it.
+    pub fn progress(
+        mut self,
+        report: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) -> CooccurrenceCounter {
+        self.progress = Some(Progress::new(report));
+        self
+    }
+
+    /// Counts all `sources` together, every source is treated as a document of
+    /// its own, so no pair spans two sources.
+    ///
+    /// The result has the shape `(alphabet, alphabet, offset_back + offset_front + 1)`,
+    /// `[(a, b, d)]` is how often `b` was found `d - offset_back` characters
+    /// away from `a`. With [`CooccurrenceCounter::unknown`] both alphabet
+    /// axes are one longer.
+    pub fn count(&self, sources: &[Source]) -> Result<Array3<u64>> {
+        validate_chunksize(
+            self.reading.chunksize,
+            self.offset_back,
+            self.offset_front,
+            self.reading.decoding,
+        )?;
+        self.gather_chunked(sources)
+    }
+
+    /// Shorthand for counting a single in memory text.
+    pub fn count_text(&self, text: &str) -> Result<Array3<u64>> {
+        self.count(&[Source::Memory(text.as_bytes().to_vec())])
+    }
+
+    fn gather_chunked(&self, sources: &[Source]) -> Result<Array3<u64>> {
+        let (offset_back, offset_front) = (self.offset_back, self.offset_front);
+        let chunks = plan(sources, self.reading.chunksize);
+        let num_chunks = chunks.len();
+        let alphabet = match self.unknown {
+            true => Alphabet::with_unknown(self.alphabet.clone()),
+            false => Alphabet::new(self.alphabet.clone()),
+        };
+        let size = alphabet.len() + self.unknown as usize;
+        let shape = (size, size, offset_back + offset_front + 1);
+        let counter = AtomicUsize::new(0);
+
+        let pool = self.reading.pool()?;
+
+        // Every worker adds its chunks onto its own accumulator, the accumulators
+        // are only summed up once all chunks are done.
+        pool.install(|| {
+            chunks
+                .par_iter()
+                .try_fold(
+                    || Array3::<u64>::zeros(shape),
+                    |mut data, &(document, start, end)| -> Result<Array3<u64>> {
+                        // Every chunk owns the centres in [start, end) but also reads
+                        // offset_back/offset_front characters of its neighbours so that
+                        // pairs crossing a chunk border are still counted exactly once.
+                        let chunk = read_chunk(
+                            &sources[document],
+                            start,
+                            end,
+                            offset_back,
+                            offset_front,
+                            self.reading.decoding,
+                            &self.reading.preprocessing,
+                        )?;
+
+                        line_process(
+                            &chunk.characters.indices(&alphabet),
+                            chunk.centres,
+                            &mut data,
+                            offset_back,
+                            offset_front,
+                        );
+
+                        let done = counter.fetch_add(1, Ordering::Relaxed) + 1;
+                        Progress::report(&self.progress, done, num_chunks);
+                        Ok(data)
+                    },
+                )
+                .try_reduce(
+                    || Array3::<u64>::zeros(shape),
+                    |mut sum, data| {
+                        sum += &data;
+                        Ok(sum)
+                    },
+                )
+        })
     }
-    thread_spawner.join().unwrap();
-    return final_sum;
 }
 
+// `indices` holds the alphabet index of every character (or OUTSIDE), so
+// counting is nothing but adding up at a computed offset.
 fn line_process(
-    buffer: &Vec<char>,
-    acceptable: &Arc<Vec<char>>,
-    offset_back: isize,
-    offset_front: isize,
-    index_map: &Arc<HashMap<char, usize>>,
-) -> ArrayBase<OwnedRepr<u64>, Dim<[usize; 3]>> {
-    let mut data = Array3::<u64>::zeros((
-        acceptable.len(),
-        acceptable.len(),
-        offset_back as usize + offset_front as usize + 1,
-    ));
-
-    if buffer.len() > offset_back as usize + offset_front as usize {
-        for (counter, &character) in buffer.iter().enumerate().skip(offset_back as usize) {
-            if acceptable.contains(&character) {
+    indices: &[u32],
+    centres: Range<usize>,
+    data: &mut Array3<u64>,
+    offset_back: usize,
+    offset_front: usize,
+) {
+    let alphabet_len = data.shape()[0];
+    let window = offset_back + offset_front + 1;
+    let counts = data.as_slice_mut().expect("Accumulator is not contiguous");
+
+    for counter in centres {
+        let base = indices[counter];
+        if base == OUTSIDE {
+            continue;
+        }
+        let row = base as usize * alphabet_len;
+        let first = counter.saturating_sub(offset_back);
+        let last = (counter + offset_front).min(indices.len() - 1);
+        for (position, &found) in (first..=last).zip(&indices[first..=last]) {
+            if position != counter && found != OUTSIDE {
+                let distance = position + offset_back - counter;
+                counts[(row + found as usize) * window + distance] += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{Decoding, Encoding, InvalidBytes};
+    use crate::mapping::Mapping;
+    use crate::preprocess::{Form, Preprocessing};
+    use crate::testing::{random_text, random_text_from};
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Write;
+    use unicode_normalization::UnicodeNormalization;
+
+    // The counting loop as it was before the lookup tables, kept as reference
+    fn naive_count(
+        buffer: &[char],
+        acceptable: &[char],
+        offset_back: isize,
+        offset_front: isize,
+    ) -> Array3<u64> {
+        let index_map: HashMap<char, usize> = acceptable
+            .iter()
+            .enumerate()
+            .map(|(index, &ch)| (ch, index))
+            .collect();
+        let mut data = Array3::<u64>::zeros((
+            acceptable.len(),
+            acceptable.len(),
+            offset_back as usize + offset_front as usize + 1,
+        ));
+        for (counter, character) in buffer.iter().enumerate() {
+            if acceptable.contains(character) {
                 for offset in -offset_back..=offset_front {
-                    if offset != 0 {
-                        let suround_char_index = counter as isize + offset;
-                        if suround_char_index >= 0 && (suround_char_index as usize) < buffer.len() {
-                            let found_char = buffer[suround_char_index as usize];
-                            if acceptable.contains(&found_char) {
-                                let point = data.get_mut((
-                                    *index_map.get(&character).unwrap(),
-                                    *index_map.get(&found_char).unwrap(),
-                                    (offset + offset_back) as usize,
-                                ));
-
-                                match point {
-                                    Some(point) => *point += 1,
-                                    None => eprintln!(
-                                        "data at point {}|{}|{} is not accessible",
-                                        index_map.get(&character).unwrap(),
-                                        index_map.get(&found_char).unwrap(),
-                                        offset as usize + offset_back as usize
-                                    ),
-                                }
-                            }
+                    let suround_char_index = counter as isize + offset;
+                    if offset != 0
+                        && suround_char_index >= 0
+                        && (suround_char_index as usize) < buffer.len()
+                    {
+                        let found_char = buffer[suround_char_index as usize];
+                        if acceptable.contains(&found_char) {
+                            data[(
+                                index_map[character],
+                                index_map[&found_char],
+                                (offset + offset_back) as usize,
+                            )] += 1;
                         }
                     }
                 }
             }
         }
+        data
+    }
+
+    #[test]
+    fn chunked_counts_match_single_pass() {
+        let acceptable_types: Vec<char> = "abcdefgh .äßд語😀\u{fffd}".chars().collect();
+        let text = random_text(5_000, 0x5eed);
+        let path = std::env::temp_dir().join(format!("gather_chunks_{}.txt", std::process::id()));
+        File::create(&path).unwrap().write_all(&text).unwrap();
+
+        let decodings = [
+            Decoding::new(Encoding::Latin1, InvalidBytes::Strict),
+            Decoding::new(Encoding::Utf8, InvalidBytes::Lossy),
+            Decoding::new(Encoding::Utf8, InvalidBytes::Skip),
+        ];
+        for ((offset_back, offset_front), decoding) in [(3, 3), (0, 5), (7, 1)]
+            .into_iter()
+            .flat_map(|offsets| decodings.map(|decoding| (offsets, decoding)))
+        {
+            let buffer = decoding.decode(&text, 0).unwrap();
+            let expected = naive_count(&buffer, &acceptable_types, offset_back, offset_front);
+
+            for (chunksize, threads, mmap) in [
+                (1, 1, false),
+                (2, 3, true),
+                (7, 4, false),
+                (64, 1, true),
+                (64, 8, false),
+                (20_000, 2, true),
+            ] {
+                let data = CooccurrenceCounter::new(acceptable_types.clone())
+                    .offset_back(offset_back as usize)
+                    .offset_front(offset_front as usize)
+                    .read_options(
+                        ReadOptions::new()
+                            .threads(threads)
+                            .chunksize(chunksize)
+                            .decoding(decoding),
+                    )
+                    .gather_chunked(&[Source::new(File::open(&path).unwrap(), mmap).unwrap()])
+                    .unwrap();
+                assert_eq!(data, expected, "chunksize {chunksize} {decoding:?}");
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unknown_characters_share_one_bucket() {
+        let acceptable_types: Vec<char> = "abc .ä".chars().collect();
+        let text = random_text(3_000, 0x0dd);
+        let decoding = Decoding::new(Encoding::Latin1, InvalidBytes::Strict);
+        for decoding in [decoding, Decoding::new(Encoding::Utf8, InvalidBytes::Lossy)] {
+            // Everything outside of the alphabet becomes one character after it
+            let unknown = '\u{e000}';
+            let buffer: Vec<char> = decoding
+                .decode(&text, 0)
+                .unwrap()
+                .into_iter()
+                .map(|c| match acceptable_types.contains(&c) {
+                    true => c,
+                    false => unknown,
+                })
+                .collect();
+            let mut with_unknown = acceptable_types.clone();
+            with_unknown.push(unknown);
+            let expected = naive_count(&buffer, &with_unknown, 2, 3);
+
+            for chunksize in [1, 64, 10_000] {
+                let data = CooccurrenceCounter::new(acceptable_types.clone())
+                    .offset_back(2)
+                    .offset_front(3)
+                    .read_options(ReadOptions::new().chunksize(chunksize).decoding(decoding))
+                    .unknown(true)
+                    .gather_chunked(&[Source::Memory(text.clone())])
+                    .unwrap();
+                assert_eq!(data, expected, "chunksize {chunksize} {decoding:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn documents_are_counted_separately() {
+        let acceptable_types: Vec<char> = "abcdefgh .äßд語😀".chars().collect();
+        let decoding = Decoding::new(Encoding::Utf8, InvalidBytes::Lossy);
+        let documents = [
+            random_text(300, 1),
+            random_text(17, 2),
+            random_text(1000, 3),
+        ];
+
+        let mut expected =
+            Array3::<u64>::zeros((acceptable_types.len(), acceptable_types.len(), 7));
+        for document in &documents {
+            expected += &naive_count(
+                &decoding.decode(document, 0).unwrap(),
+                &acceptable_types,
+                3,
+                3,
+            );
+        }
+        let sources: Vec<Source> = documents
+            .iter()
+            .map(|document| Source::Memory(document.clone()))
+            .collect();
+        let data = CooccurrenceCounter::new(acceptable_types)
+            .offset_back(3)
+            .offset_front(3)
+            .read_options(
+                ReadOptions::new()
+                    .threads(4)
+                    .chunksize(64)
+                    .decoding(decoding),
+            )
+            .gather_chunked(&sources)
+            .unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn preprocessed_chunks_match_whole_text() {
+        let acceptable_types: Vec<char> = "abcfiu .\näöü\u{ac01}".chars().collect();
+        let text = random_text_from(
+            3_000,
+            0xf01d,
+            "aAbBcC .\n\t  \u{308}\u{301}\u{323}äÖüﬁİ\u{1100}\u{1161}\u{11a8}",
+        );
+        let decoding = Decoding::new(Encoding::Utf8, InvalidBytes::Lossy);
+        let decoded = decoding.decode(&text, 0).unwrap();
+
+        let nfc = Preprocessing {
+            form: Some(Form::Nfc),
+            ..Preprocessing::default()
+        };
+        // Normalizing segment by segment is the same as normalizing at once
+        assert_eq!(
+            nfc.process(&decoded),
+            decoded.iter().copied().nfc().collect::<Vec<_>>()
+        );
+
+        let mapping = Mapping::new()
+            .map("bB".chars(), 'a')
+            .unwrap()
+            .map(".\t".chars(), ' ')
+            .unwrap();
+        for preprocessing in [
+            nfc,
+            Preprocessing {
+                lowercase: true,
+                ..Preprocessing::default()
+            },
+            Preprocessing {
+                collapse_whitespace: true,
+                ..Preprocessing::default()
+            },
+            Preprocessing {
+                lowercase: true,
+                form: Some(Form::Nfkc),
+                strip_diacritics: true,
+                collapse_whitespace: true,
+                ..Preprocessing::default()
+            },
+            Preprocessing {
+                mapping: mapping.clone(),
+                ..Preprocessing::default()
+            },
+            Preprocessing {
+                lowercase: true,
+                collapse_whitespace: true,
+                mapping,
+                ..Preprocessing::default()
+            },
+        ] {
+            let expected = naive_count(&preprocessing.process(&decoded), &acceptable_types, 2, 3);
+            for (chunksize, threads) in [(1, 1), (5, 3), (64, 2), (10_000, 1)] {
+                let data = CooccurrenceCounter::new(acceptable_types.clone())
+                    .offset_back(2)
+                    .offset_front(3)
+                    .read_options(
+                        ReadOptions::new()
+                            .threads(threads)
+                            .chunksize(chunksize)
+                            .preprocessing(preprocessing.clone()),
+                    )
+                    .gather_chunked(&[Source::Memory(text.clone())])
+                    .unwrap();
+                assert_eq!(data, expected, "chunksize {chunksize} {preprocessing}");
+            }
+        }
     }
-    return data;
 }
@@ -0,0 +1,238 @@
+use crate::compression::{Compression, MAGIC_LEN};
+use crate::source::Source;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// One document given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Stdin,
+    Path(PathBuf),
+}
+
+impl Input {
+    /// Opens the document, decompressing it first if necessary.
+    pub fn open(&self, mmap: bool) -> io::Result<Source> {
+        match self {
+            Input::Stdin => read_stream(io::stdin().lock(), mmap),
+            Input::Path(path) => {
+                let mut file = File::open(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+                let mut header = Vec::with_capacity(MAGIC_LEN);
+                (&mut file)
+                    .take(MAGIC_LEN as u64)
+                    .read_to_end(&mut header)?;
+                file.rewind()?;
+                match Compression::detect(&header, Some(path)) {
+                    Some(compression) => spool(compression.decoder(file)?, mmap),
+                    None => Source::new(file, mmap),
+                }
+            }
+        }
+    }
+}
+
+// Streams like stdin are never kept in memory, they can be far larger than it.
+fn read_stream(mut reader: impl Read, mmap: bool) -> io::Result<Source> {
+    let mut header = Vec::with_capacity(MAGIC_LEN);
+    (&mut reader)
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut header)?;
+    let compression = Compression::detect(&header, None);
+    let reader = io::Cursor::new(header).chain(reader);
+    match compression {
+        Some(compression) => spool(compression.decoder(reader)?, mmap),
+        None => spool(reader, mmap),
+    }
+}
+
+// Compressed input and streams can not be read at arbitrary positions, so
+// they are copied once into an anonymous temporary file (in TMPDIR) which is
+// removed again as soon as it is closed.
+fn spool(mut reader: impl Read, mmap: bool) -> io::Result<Source> {
+    let mut file = tempfile::tempfile()?;
+    io::copy(&mut reader, &mut file)?;
+    Source::new(file, mmap)
+}
+
+/// Turns the -i arguments into the list of documents to read. `-` is stdin,
+/// directories are walked recursively and arguments that do not exist but
+/// contain a wildcard are expanded as glob pattern.
+pub fn resolve(arguments: &[String]) -> io::Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+    for argument in arguments {
+        if argument == "-" {
+            if inputs.contains(&Input::Stdin) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "stdin can only be given once",
+                ));
+            }
+            inputs.push(Input::Stdin);
+            continue;
+        }
+        let path = Path::new(argument);
+        if !path.exists() && argument.contains(['*', '?', '[']) {
+            let paths =
+                glob::glob(argument).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let mut matched = false;
+            for path in paths {
+                let path = path.map_err(io::Error::from)?;
+                collect(&path, &mut inputs)?;
+                matched = true;
+            }
+            if !matched {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{argument} does not match any file"),
+                ));
+            }
+            continue;
+        }
+        collect(path, &mut inputs)?;
+    }
+    Ok(inputs)
+}
+
+fn collect(path: &Path, inputs: &mut Vec<Input>) -> io::Result<()> {
+    if !path.is_dir() {
+        // Opening it later reports a missing file with its name
+        inputs.push(Input::Path(path.to_path_buf()));
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for entry in entries {
+        collect(&entry, inputs)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(compression: Compression, text: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        match compression {
+            Compression::Gzip => {
+                flate2::read::GzEncoder::new(text, flate2::Compression::fast())
+                    .read_to_end(&mut compressed)
+                    .unwrap();
+            }
+            Compression::Zstd => compressed = zstd::encode_all(text, 3).unwrap(),
+            Compression::Bzip2 => {
+                bzip2::read::BzEncoder::new(text, bzip2::Compression::fast())
+                    .read_to_end(&mut compressed)
+                    .unwrap();
+            }
+            Compression::Xz => {
+                xz2::read::XzEncoder::new(text, 6)
+                    .read_to_end(&mut compressed)
+                    .unwrap();
+            }
+        }
+        compressed
+    }
+
+    fn argument(path: &Path) -> String {
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn arguments_are_resolved() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path();
+        fs::create_dir(root.join("nested")).unwrap();
+        for name in ["b.txt", "a.txt", "nested/c.txt", "nested/d.gz"] {
+            File::create(root.join(name)).unwrap();
+        }
+        let paths = |names: &[&str]| -> Vec<Input> {
+            names
+                .iter()
+                .map(|name| Input::Path(root.join(name)))
+                .collect()
+        };
+
+        // Directories are walked recursively in sorted order
+        assert_eq!(
+            resolve(&[argument(root)]).unwrap(),
+            paths(&["a.txt", "b.txt", "nested/c.txt", "nested/d.gz"])
+        );
+        let mut expected = vec![Input::Stdin];
+        expected.extend(paths(&["a.txt", "b.txt", "nested/c.txt"]));
+        assert_eq!(
+            resolve(&[
+                "-".to_string(),
+                argument(&root.join("*.txt")),
+                argument(&root.join("nested/*.txt")),
+            ])
+            .unwrap(),
+            expected
+        );
+        // Missing files are only reported once they are opened
+        assert_eq!(
+            resolve(&[argument(&root.join("missing.txt"))]).unwrap(),
+            paths(&["missing.txt"])
+        );
+
+        let twice = resolve(&["-".to_string(), argument(root), "-".to_string()]);
+        assert_eq!(twice.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let unmatched = resolve(&[argument(&root.join("*.xz"))]);
+        assert_eq!(unmatched.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn compressed_inputs_are_decompressed() {
+        let directory = tempfile::tempdir().unwrap();
+        let text = "compressed text \u{e4}\n".repeat(500).into_bytes();
+        for (compression, extension) in [
+            (Compression::Gzip, "gz"),
+            (Compression::Zstd, "zst"),
+            (Compression::Bzip2, "bz2"),
+            (Compression::Xz, "xz"),
+        ] {
+            let compressed = compress(compression, &text);
+            // Recognised by the extension and the magic bytes, by the magic
+            // bytes alone and as stdin
+            let named = directory.path().join(format!("named.{extension}"));
+            let misnamed = directory.path().join(format!("misnamed_{extension}.txt"));
+            fs::write(&named, &compressed).unwrap();
+            fs::write(&misnamed, &compressed).unwrap();
+            for mmap in [false, true] {
+                for source in [
+                    Input::Path(named.clone()).open(mmap).unwrap(),
+                    Input::Path(misnamed.clone()).open(mmap).unwrap(),
+                    read_stream(&compressed[..], mmap).unwrap(),
+                ] {
+                    let read = source.read(0..source.len()).unwrap();
+                    assert!(read == text, "{compression:?}");
+                }
+            }
+        }
+        // Plain text is left as it is, even if it looks like a magic number
+        let plain = directory.path().join("plain.txt");
+        fs::write(&plain, "BZh9 plain text").unwrap();
+        let source = Input::Path(plain).open(false).unwrap();
+        assert_eq!(
+            source.read(0..source.len()).unwrap(),
+            &b"BZh9 plain text"[..]
+        );
+    }
+
+    #[test]
+    fn streams_are_spooled() {
+        let text = b"streamed text".repeat(1000);
+        for mmap in [false, true] {
+            let source = read_stream(&text[..], mmap).unwrap();
+            assert!(!matches!(source, Source::Memory(_)));
+            assert_eq!(source.read(0..source.len()).unwrap(), &text[..]);
+        }
+        // Shorter than the magic bytes
+        let source = read_stream(&b"ab"[..], false).unwrap();
+        assert_eq!(source.read(0..source.len()).unwrap(), &b"ab"[..]);
+    }
+}
@@ -0,0 +1,47 @@
+//! Analyses how often characters appear next to each other in text corpora.
+//!
+//! The binary is a thin command line wrapper around this library:
+//!
+//! * [`CooccurrenceCounter`] counts for every pair of characters of an alphabet
+//!   how often they are found at every distance from each other.
+//! * [`Normalizer`]s turn those counts into normalized values, see
+//!   [`normalize::Registry`] for choosing them by name, [`pmi::association`]
+//!   turns them into pointwise mutual information.
+//! * [`WindowExtractor`] collects the characters surrounding every occurrence
+//!   of a character, e.g. as training data.
+//! * [`FrequencyCounter`] counts every character of a corpus so that the
+//!   alphabet can be chosen from it with a [`Selection`].
+//!
+//! Text is read from [`Source`]s, which can be created from files (see
+//! [`input`] for directories, globs, stdin and compressed files) or from
+//! memory.
+
+pub mod alphabet;
+pub mod char_dataset;
+mod chunk;
+pub mod compression;
+pub mod decode;
+pub mod discover;
+pub mod error;
+pub mod gather;
+pub mod input;
+pub mod mapping;
+pub mod metadata;
+pub mod normalize;
+pub mod pmi;
+pub mod preprocess;
+pub mod source;
+#[cfg(test)]
+mod testing;
+mod threading;
+
+pub use char_dataset::WindowExtractor;
+pub use chunk::ReadOptions;
+pub use decode::{Decoding, Encoding, InvalidBytes};
+pub use discover::{FrequencyCounter, Selection};
+pub use error::{Error, Result};
+pub use gather::CooccurrenceCounter;
+pub use mapping::Mapping;
+pub use normalize::Normalizer;
+pub use preprocess::Preprocessing;
+pub use source::Source;
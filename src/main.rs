@@ -1,20 +1,21 @@
-use clap::{Parser, Subcommand};
-use gather::gather_characters;
-use hdf5::{
-    self,
-    types::{VarLenArray, VarLenAscii},
+use character_gather::{
+    alphabet::{self, Preset},
+    char_dataset::{self, Format},
+    input, mapping, metadata,
+    normalize::{self, EmptyPolicy, Grouping, Registry},
+    pmi,
+    preprocess::Form,
+    CooccurrenceCounter, Decoding, Encoding, Error, FrequencyCounter, InvalidBytes, Mapping,
+    Preprocessing, ReadOptions, Result, Selection, Source, WindowExtractor,
 };
-use ndarray::{Array2, Array3, ArrayBase, Dim, OwnedRepr};
-use normalize::normalizer_sum_one;
+use clap::builder::{PossibleValue, PossibleValuesParser, TypedValueParser};
+use clap::{Parser, Subcommand, ValueEnum};
+use ndarray::Array3;
 use std::fs::File as StdFile;
-mod char_dataset;
-mod gather;
-mod normalize;
-mod threading;
-use std::sync::{mpsc, Arc};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
 use std::fs::OpenOptions;
-use std::io::Write;
 
 #[derive(Parser)]
 #[command(name = "Character Gather")]
@@ -30,216 +31,768 @@ struct Args {
 #[derive(Subcommand)]
 enum Commands {
     AbsoluteCharRelation {
-        #[clap(short, long, value_parser, num_args = 1.., value_delimiter = ',')]
-        acceptable_types: Vec<char>,
+        #[command(flatten)]
+        alphabet: AlphabetArgs,
+        #[command(flatten)]
+        selection: SelectionArgs,
         #[arg(long, default_value_t = 4)]
         offset_back: isize,
         #[arg(long, default_value_t = 4)]
         offset_front: isize,
-        #[arg(short)]
-        input: String,
+        #[arg(
+            short,
+            num_args = 1..,
+            required = true,
+            help = "Files, directories or glob patterns to read, - reads stdin. Every file is a document of its own"
+        )]
+        input: Vec<String>,
         #[arg(short)]
         output: String,
         #[arg(short)]
         threads: usize,
+        #[command(flatten)]
+        reading: InputArgs,
+        #[command(flatten)]
+        preprocessing: PreprocessingArgs,
+        #[arg(
+            long,
+            help = "Count every character outside of the alphabet as one extra character <UNK> after the last one"
+        )]
+        unknown: bool,
     },
     Normalize {
-        #[arg(short)]
+        #[arg(short, required_unless_present = "list")]
+        input: Option<String>,
+        #[arg(
+            long,
+            default_value = "min-max",
+            help = "Name of the normalization, see --list"
+        )]
+        method: String,
+        #[arg(
+            long = "param",
+            value_parser = parse_parameter,
+            help = "Parameter of the normalization as NAME=VALUE, can be repeated"
+        )]
+        params: Vec<(String, String)>,
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            default_value = "found",
+            help = "Axes normalized together, e.g. found,distance normalizes every base character across all distances"
+        )]
+        over: Vec<Over>,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = EmptyPolicy::Nan,
+            help = "What is written for groups without a defined result, e.g. characters that were never found or equal counts with min-max"
+        )]
+        empty: EmptyPolicy,
+        #[arg(long, help = "List the normalizations and exit")]
+        list: bool,
+    },
+    Pmi {
+        #[arg(
+            short,
+            help = "HDF5 file with absolute_data, the results are written into it"
+        )]
         input: String,
         #[arg(
             long,
-            default_value_t = 0,
-            help = "Set the normalization type:\n\t0: Value = (X - min)/(max - min)\n\t1: Value = X/sum\n\t:2 Value = X - mean\n\t3: Value = X / max(X)\n\t4: Value = (X - mean) / std_deviation"
+            default_value_t = 1.0,
+            help = "Smoothing exponent of the found character probabilities for PPMI, 0.75 is common, 1 turns it off"
         )]
-        n_type: u8,
+        alpha: f64,
     },
     CharDataset {
-        #[clap(short, long, value_parser, num_args = 1.., value_delimiter = ',')]
-        acceptable_types: Vec<char>,
+        #[command(flatten)]
+        alphabet: AlphabetArgs,
+        #[command(flatten)]
+        selection: SelectionArgs,
         #[arg(long, default_value_t = 4)]
         offset_back: isize,
         #[arg(long, default_value_t = 4)]
         offset_front: isize,
-        #[arg(short)]
-        input: String,
+        #[arg(
+            short,
+            num_args = 1..,
+            required = true,
+            help = "Files, directories or glob patterns to read, - reads stdin. Every file is a document of its own"
+        )]
+        input: Vec<String>,
         #[arg(short)]
         output: String,
         #[arg(short)]
         threads: usize,
+        #[command(flatten)]
+        reading: InputArgs,
+        #[command(flatten)]
+        preprocessing: PreprocessingArgs,
+        #[arg(
+            long,
+            help = "Keep windows with characters outside of the alphabet, written as <UNK>"
+        )]
+        unknown: bool,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = Format::Csv,
+            help = "csv writes <OUTPUT>_<character>.csv files and the alphabet to <OUTPUT>_alphabet.txt, hdf5 writes all windows into the file <OUTPUT>"
+        )]
+        format: Format,
     },
+    Discover {
+        #[arg(
+            short,
+            num_args = 1..,
+            required = true,
+            help = "Files, directories or glob patterns to read, - reads stdin"
+        )]
+        input: Vec<String>,
+        #[arg(
+            short,
+            default_value_t = 0,
+            help = "Threads to use, 0 uses one per core"
+        )]
+        threads: usize,
+        #[command(flatten)]
+        reading: InputArgs,
+        #[command(flatten)]
+        preprocessing: PreprocessingArgs,
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+}
+
+// -a, presets and the file can be combined, their characters are joined
+#[derive(clap::Args)]
+#[group(required = true, multiple = true)]
+struct AlphabetArgs {
+    #[clap(short, long, value_parser, num_args = 1.., value_delimiter = ',')]
+    acceptable_types: Option<Vec<char>>,
+    #[arg(long, value_parser = alphabet_choices(), num_args = 1.., value_delimiter = ',')]
+    alphabet: Vec<AlphabetChoice>,
+    #[arg(
+        long,
+        help = "File with one character per line, \\n, \\r, \\t, \\\\ and \\u{2014} can be used for characters that are hard to type"
+    )]
+    alphabet_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AlphabetChoice {
+    Auto,
+    Preset(Preset),
+}
+
+// auto and every preset, so the presets are only listed once in alphabet.rs
+fn alphabet_choices() -> impl TypedValueParser<Value = AlphabetChoice> {
+    let auto = PossibleValue::new("auto")
+        .help("Choose the alphabet from the character frequencies of the input like discover does");
+    let presets = Preset::value_variants()
+        .iter()
+        .filter_map(|preset| preset.to_possible_value());
+    PossibleValuesParser::new(std::iter::once(auto).chain(presets)).map(|name| {
+        match name.as_str() {
+            "auto" => AlphabetChoice::Auto,
+            name => AlphabetChoice::Preset(
+                Preset::from_str(name, false).expect("Only presets are left"),
+            ),
+        }
+    })
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Over {
+    /// The base characters
+    Base,
+    /// The found characters
+    Found,
+    /// The distances
+    Distance,
+    /// All three axes, the whole counts at once
+    All,
+}
+
+#[derive(clap::Args)]
+struct InputArgs {
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    encoding: Encoding,
+    #[arg(long, value_enum, default_value_t = InvalidBytes::Lossy)]
+    invalid_bytes: InvalidBytes,
+    #[arg(
+        long,
+        help = "Memory map the input instead of reading it chunk by chunk"
+    )]
+    mmap: bool,
+    #[arg(
+        long,
+        default_value = "16K",
+        value_parser = parse_size,
+        help = "Bytes read at once, with an optional K, M or G suffix"
+    )]
+    chunk_size: usize,
+}
+
+impl InputArgs {
+    fn decoding(&self) -> Decoding {
+        Decoding::new(self.encoding, self.invalid_bytes)
+    }
 }
 
-fn main() -> hdf5::Result<()> {
+#[derive(clap::Args)]
+struct PreprocessingArgs {
+    #[arg(long, help = "Fold every character to lowercase")]
+    lowercase: bool,
+    #[arg(
+        long,
+        value_enum,
+        help = "Unicode normalization form to bring the text into"
+    )]
+    normalization: Option<Form>,
+    #[arg(long, help = "Remove diacritics, e.g. ä becomes a")]
+    strip_diacritics: bool,
+    #[arg(long, help = "Replace every run of whitespace with a single space")]
+    collapse_whitespace: bool,
+    #[arg(
+        long,
+        help = "File replacing characters with others, every line holds the characters, a space and their replacement"
+    )]
+    mapping_file: Option<PathBuf>,
+}
+
+impl PreprocessingArgs {
+    fn preprocessing(&self) -> Result<Preprocessing> {
+        let mapping = match &self.mapping_file {
+            Some(path) => mapping::read_mapping_file(path)?,
+            None => Mapping::new(),
+        };
+        Ok(Preprocessing {
+            lowercase: self.lowercase,
+            form: self.normalization,
+            strip_diacritics: self.strip_diacritics,
+            collapse_whitespace: self.collapse_whitespace,
+            mapping,
+        })
+    }
+}
+
+#[derive(clap::Args)]
+#[group(multiple = false)]
+struct SelectionArgs {
+    #[arg(long, help = "Choose the N most frequent characters")]
+    top: Option<usize>,
+    #[arg(long, help = "Choose every character found at least N times")]
+    min_count: Option<u64>,
+    #[arg(
+        long,
+        help = "Choose the most frequent characters until they make up P percent of the input"
+    )]
+    coverage: Option<f64>,
+}
+
+impl SelectionArgs {
+    fn selection(&self) -> Result<Option<Selection>> {
+        let selection = match (self.top, self.min_count, self.coverage) {
+            (Some(n), _, _) => Selection::Top(n),
+            (_, Some(min), _) => Selection::MinCount(min),
+            (_, _, Some(percent)) => Selection::Coverage(percent),
+            _ => return Ok(None),
+        };
+        selection.validate()?;
+        Ok(Some(selection))
+    }
+}
+
+fn main() -> ExitCode {
     let args = Args::parse();
 
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(args: Args) -> Result<()> {
     match args.command {
         Some(Commands::AbsoluteCharRelation {
-            acceptable_types,
+            alphabet,
+            selection,
             offset_back,
             offset_front,
             input,
             output,
             threads,
+            reading,
+            preprocessing,
+            unknown,
         }) => {
-            let file = StdFile::open(input).expect("Could not open input file");
+            let offset_back = validate_offset("offset-back", offset_back)?;
+            let offset_front = validate_offset("offset-front", offset_front)?;
+            let sources = open_sources(&input, reading.mmap)?;
+            let preprocessing = preprocessing.preprocessing()?;
+            let read_options = ReadOptions::new()
+                .threads(threads)
+                .chunksize(reading.chunk_size)
+                .decoding(reading.decoding())
+                .preprocessing(preprocessing.clone());
+            let frequencies = FrequencyCounter::new().read_options(read_options.clone());
+            let (acceptable_types, chosen_by) =
+                choose_alphabet(alphabet, selection, &sources, frequencies)?;
 
-            let hdf5_file = hdf5::File::create(output).expect("Could not create file");
+            let hdf5_file = hdf5::File::create(output)?;
+            let size = acceptable_types.len() + unknown as usize;
             let dataset = hdf5_file
                 .new_dataset::<u64>()
-                .shape((
-                    acceptable_types.len(),
-                    acceptable_types.len(),
-                    offset_back as usize + offset_front as usize + 1,
-                ))
-                .create("absolute_data")
-                .expect("could not create base");
-
-            dataset
-                .new_attr::<VarLenAscii>()
-                .shape(())
-                .create("acceptable_types")?
-                .write_scalar(
-                    &VarLenAscii::from_ascii(&acceptable_types.iter().collect::<String>()).unwrap(),
-                )
-                .expect("Could not create acceptable types attribute");
-            let data =
-                gather_characters(acceptable_types, offset_back, offset_front, file, threads);
-            dataset.write(&data).expect("Could not write the fiel");
-            dataset
-                .new_attr::<u64>()
-                .shape(())
-                .create("offset_back")?
-                .write_scalar(&offset_back)
-                .expect("Could not create offset_back attribute");
-            dataset
-                .new_attr::<u64>()
-                .shape(())
-                .create("offset_front")?
-                .write_scalar(&offset_front)
-                .expect("Could not create offset_front attribute");
+                .shape((size, size, offset_back + offset_front + 1))
+                .create("absolute_data")?;
+
+            metadata::write_alphabet(&dataset, &acceptable_types)?;
+            metadata::write_string(&dataset, "alphabet_selection", &chosen_by)?;
+            metadata::write_string(&dataset, "preprocessing", &preprocessing.to_string())?;
+            metadata::write_string(&dataset, "mapping", &preprocessing.mapping.to_string())?;
+            metadata::attribute::<bool>(&dataset, "unknown")?.write_scalar(&unknown)?;
+            let data = CooccurrenceCounter::new(acceptable_types)
+                .offset_back(offset_back)
+                .offset_front(offset_front)
+                .read_options(read_options)
+                .unknown(unknown)
+                .progress(print_progress)
+                .count(&sources)?;
+            dataset.write(&data)?;
+            metadata::attribute::<u64>(&dataset, "offset_back")?
+                .write_scalar(&(offset_back as u64))?;
+            metadata::attribute::<u64>(&dataset, "offset_front")?
+                .write_scalar(&(offset_front as u64))?;
         }
-        Some(Commands::Normalize { input, n_type }) => {
-            let hdf5_file = hdf5::File::open_as(input, hdf5::file::OpenMode::ReadWrite)
-                .expect("Could not find file {input}");
-            let absolute_dataset = match hdf5_file.dataset("/absolute_data/") {
-                Ok(dataset) => dataset,
-                Err(e) => match hdf5_file.dataset("/results/") {
-                    Ok(dataset) => dataset,
-                    Err(oe) => panic!("Could not find the dataset in this file: {e} | {oe}"),
-                },
-            };
-            let offset_front: u64 = absolute_dataset.attr("offset_front")?.read_scalar()?;
-            let offset_back: u64 = absolute_dataset.attr("offset_back")?.read_scalar()?;
-            let acceptable_types: VarLenAscii =
-                absolute_dataset.attr("acceptable_types")?.read_scalar()?;
-            let acceptable_types = acceptable_types
-                .as_bytes()
-                .iter()
-                .map(|c| *c as char)
-                .collect::<Vec<char>>();
-
-            let data: Array3<u64> = absolute_dataset.read()?;
-            let normalized_data = match n_type {
-                0 => normalize::normalizer_min_max(data),
-                1 => normalize::normalizer_sum_one(data),
-                2 => normalize::normalizer_minus_mean(data),
-                3 => normalize::normalizer_divide_max(data),
-                4 => normalize::normalizer_z_score(data),
-                _ => panic!("No such normalizer implemented"),
+        Some(Commands::Normalize {
+            input,
+            method,
+            params,
+            over,
+            empty,
+            list,
+        }) => {
+            let registry = Registry::default();
+            if list {
+                for (name, description) in registry.list() {
+                    println!("{name}\t{description}");
+                }
+                return Ok(());
+            }
+            let input = input.expect("clap requires the input without --list");
+            let normalizer = registry.create(&method, &params.into_iter().collect())?;
+            if empty == EmptyPolicy::Uniform && !normalizer.is_distribution() {
+                return Err(Error::Validation(format!(
+                    "--empty uniform needs a normalizer that turns groups into distributions like sum-one, not {}",
+                    normalizer.name()
+                )));
+            }
+            let hdf5_file = hdf5::File::open_as(input, hdf5::file::OpenMode::ReadWrite)?;
+            let counts = read_counts(&hdf5_file)?;
+            let grouping = Grouping {
+                base: over.iter().any(|o| matches!(o, Over::Base | Over::All)),
+                found: over.iter().any(|o| matches!(o, Over::Found | Over::All)),
+                distance: over.iter().any(|o| matches!(o, Over::Distance | Over::All)),
             };
+            let offset_back = counts.offset_back as usize;
+            let (normalized_data, summary) = normalize::handle_empty(
+                &counts.data,
+                normalizer.normalize(&counts.data, grouping, offset_back),
+                grouping,
+                empty,
+                offset_back,
+            );
+            println!(
+                "{} of {} groups were empty and {} degenerate ({empty})",
+                summary.empty.len(),
+                summary.groups,
+                summary.degenerate.len()
+            );
+            if empty == EmptyPolicy::Fail && !summary.empty.is_empty() {
+                return Err(Error::Validation(format!(
+                    "{} of {} groups are empty: {}",
+                    summary.empty.len(),
+                    summary.groups,
+                    summary.report(&counts.acceptable_types, offset_back)
+                )));
+            }
+            if empty == EmptyPolicy::Fail && !summary.degenerate.is_empty() {
+                return Err(Error::Validation(format!(
+                    "{} of {} groups were counted but {} has no result for them: {}",
+                    summary.degenerate.len(),
+                    summary.groups,
+                    normalizer.name(),
+                    summary.report_degenerate(&counts.acceptable_types, offset_back)
+                )));
+            }
 
             let normalized_dataset = match hdf5_file
                 .new_dataset::<f64>()
-                .shape((
-                    acceptable_types.len(),
-                    acceptable_types.len(),
-                    offset_back as usize + offset_front as usize + 1,
-                ))
+                .shape(counts.data.dim())
                 .create("normalized_data")
             {
                 Ok(dataset) => dataset,
-                Err(_) => hdf5_file
-                    .dataset("/normalized_data/")
-                    .expect("Could not open normalized_data"),
-            };
-            normalized_dataset
-                .write(&normalized_data)
-                .expect("Could not write data to dataset");
-
-            let attr = match normalized_dataset
-                .new_attr::<VarLenAscii>()
-                .shape(())
-                .create("acceptable_types")
-            {
-                Ok(attr) => attr,
-                Err(_) => normalized_dataset.attr("acceptable_types")?,
-            };
-            attr.write_scalar(
-                &VarLenAscii::from_ascii(&acceptable_types.iter().collect::<String>()).unwrap(),
-            )?;
-
-            let attr = match normalized_dataset
-                .new_attr::<u64>()
-                .shape(())
-                .create("offset_back")
-            {
-                Ok(attr) => attr,
-                Err(_) => normalized_dataset.attr("offset_back")?,
+                Err(_) => hdf5_file.dataset("/normalized_data/")?,
             };
-            attr.write_scalar(&offset_back)
-                .expect("Could not create offset_back attribute");
-            let attr = match normalized_dataset
-                .new_attr::<u64>()
-                .shape(())
-                .create("offset_front")
-            {
-                Ok(attr) => attr,
-                Err(_) => normalized_dataset.attr("offset_front")?,
-            };
-            attr.write_scalar(&offset_front)
-                .expect("Could not create offset_front attribute");
+            normalized_dataset.write(&normalized_data)?;
+
+            counts.describe(&normalized_dataset)?;
+            metadata::write_string(&normalized_dataset, "method", normalizer.name())?;
+            let params: Vec<String> = normalizer
+                .params()
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            metadata::write_string(&normalized_dataset, "parameters", &params.join(", "))?;
+            metadata::write_string(&normalized_dataset, "over", &grouping.to_string())?;
+            metadata::write_string(&normalized_dataset, "empty", &empty.to_string())?;
+            metadata::attribute::<u64>(&normalized_dataset, "empty_groups")?
+                .write_scalar(&(summary.empty.len() as u64))?;
+            metadata::attribute::<u64>(&normalized_dataset, "degenerate_groups")?
+                .write_scalar(&(summary.degenerate.len() as u64))?;
+        }
+        Some(Commands::Pmi { input, alpha }) => {
+            let hdf5_file = hdf5::File::open_as(input, hdf5::file::OpenMode::ReadWrite)?;
+            let counts = read_counts(&hdf5_file)?;
+            let association = pmi::association(&counts.data, alpha)?;
+
+            for (name, data) in [
+                ("pmi_data", &association.pmi),
+                ("ppmi_data", &association.ppmi),
+                ("npmi_data", &association.npmi),
+            ] {
+                if hdf5_file.link_exists(name) {
+                    hdf5_file.unlink(name)?;
+                }
+                let dataset = hdf5_file
+                    .new_dataset::<f64>()
+                    .shape(data.dim())
+                    .create(name)?;
+                dataset.write(data)?;
+                counts.describe(&dataset)?;
+                if name == "ppmi_data" {
+                    metadata::attribute::<f64>(&dataset, "alpha")?.write_scalar(&alpha)?;
+                }
+            }
+            for (name, data) in [
+                ("base_marginals", &association.base),
+                ("found_marginals", &association.found),
+            ] {
+                if hdf5_file.link_exists(name) {
+                    hdf5_file.unlink(name)?;
+                }
+                let dataset = hdf5_file
+                    .new_dataset::<f64>()
+                    .shape(data.dim())
+                    .create(name)?;
+                dataset.write(data)?;
+                counts.describe(&dataset)?;
+            }
         }
         Some(Commands::CharDataset {
-            acceptable_types,
+            alphabet,
+            selection,
             offset_back,
             offset_front,
             input,
             output,
             threads,
+            reading,
+            preprocessing,
+            unknown,
+            format,
         }) => {
-            let file = StdFile::open(input).expect("Could not open input file");
-
-            let pool = rayon::ThreadPoolBuilder::new()
-                .num_threads(threads)
-                .build()
-                .expect("Could not build thread builder");
-
-            let iter_acceptable_types = acceptable_types.clone();
-            let acceptable_types = Arc::new(acceptable_types);
-            let file = Arc::new(file);
-
-            pool.scope(|s| {
-                for character in iter_acceptable_types {
-                    let name = format!("{}_{}.csv", &output, &character);
-                    let result_file = StdFile::create(&name).expect("Could not create File");
-                    let mut result_file = OpenOptions::new().append(true).open(name).unwrap();
-                    let file = Arc::clone(&file);
-                    let acceptable_types = Arc::clone(&acceptable_types);
-                    s.spawn(move |_| {
-                        char_dataset::gather_dataset(
-                            character,
-                            acceptable_types,
-                            offset_back,
-                            offset_front,
-                            file.try_clone().unwrap(),
-                            result_file,
-                        );
-                    })
+            let offset_back = validate_offset("offset-back", offset_back)?;
+            let offset_front = validate_offset("offset-front", offset_front)?;
+            let sources = open_sources(&input, reading.mmap)?;
+            let preprocessing = preprocessing.preprocessing()?;
+            let read_options = ReadOptions::new()
+                .threads(threads)
+                .chunksize(reading.chunk_size)
+                .decoding(reading.decoding())
+                .preprocessing(preprocessing.clone());
+            let frequencies = FrequencyCounter::new().read_options(read_options.clone());
+            let (acceptable_types, chosen_by) =
+                choose_alphabet(alphabet, selection, &sources, frequencies)?;
+
+            let extractor = WindowExtractor::new(acceptable_types.clone())
+                .offset_back(offset_back)
+                .offset_front(offset_front)
+                .read_options(read_options)
+                .unknown(unknown)
+                .progress(print_progress);
+
+            match format {
+                Format::Csv => {
+                    let mut result_files = Vec::new();
+                    for (index, &character) in acceptable_types.iter().enumerate() {
+                        let name = csv_name(&output, index, character);
+                        StdFile::create(&name)?;
+                        result_files.push(OpenOptions::new().append(true).open(name)?);
+                    }
+                    std::fs::write(
+                        format!("{output}_alphabet.txt"),
+                        alphabet::format_alphabet(&acceptable_types),
+                    )?;
+                    char_dataset::gather_datasets(&extractor, &sources, result_files)?;
+                }
+                Format::Hdf5 => {
+                    let hdf5_file = hdf5::File::create(output)?;
+                    char_dataset::gather_hdf5(&extractor, &sources, &hdf5_file)?;
+                    for index in 0..acceptable_types.len() {
+                        let dataset = hdf5_file.dataset(&format!("windows_{index}"))?;
+                        metadata::write_string(&dataset, "alphabet_selection", &chosen_by)?;
+                        metadata::write_string(
+                            &dataset,
+                            "preprocessing",
+                            &preprocessing.to_string(),
+                        )?;
+                        metadata::write_string(
+                            &dataset,
+                            "mapping",
+                            &preprocessing.mapping.to_string(),
+                        )?;
+                    }
                 }
-            });
+            }
+        }
+        Some(Commands::Discover {
+            input,
+            threads,
+            reading,
+            preprocessing,
+            selection,
+        }) => {
+            let selection = selection.selection()?.unwrap_or(Selection::All);
+            let sources = open_sources(&input, reading.mmap)?;
+            let frequencies = FrequencyCounter::new()
+                .read_options(
+                    ReadOptions::new()
+                        .threads(threads)
+                        .chunksize(reading.chunk_size)
+                        .decoding(reading.decoding())
+                        .preprocessing(preprocessing.preprocessing()?),
+                )
+                .count(&sources)?;
+            let alphabet = selection.select(&frequencies);
+
+            let total: u64 = frequencies.iter().map(|(_, count)| count).sum();
+            let mut covered = 0;
+            for (character, count) in &frequencies {
+                let chosen = alphabet.contains(character);
+                if chosen {
+                    covered += count;
+                }
+                println!(
+                    "{}\t{character:?}\t{count}\t{:.4}%",
+                    if chosen { "*" } else { " " },
+                    *count as f64 / total as f64 * 100.0
+                );
+            }
+            println!(
+                "{} of {} characters chosen ({selection}), covering {:.4}% of the input: {:?}",
+                alphabet.len(),
+                frequencies.len(),
+                covered as f64 / total as f64 * 100.0,
+                alphabet.iter().collect::<String>()
+            );
         }
         None => eprint!("No command given, so nothing will happen"),
     }
 
     Ok(())
 }
+
+// absolute_data together with what its attributes say about it
+struct Counts {
+    data: Array3<u64>,
+    acceptable_types: Vec<char>,
+    offset_back: u64,
+    offset_front: u64,
+    unknown: bool,
+}
+
+impl Counts {
+    // Writes the attributes needed to read a dataset computed from the counts
+    fn describe(&self, dataset: &hdf5::Dataset) -> Result<()> {
+        metadata::write_alphabet(dataset, &self.acceptable_types)?;
+        metadata::attribute::<bool>(dataset, "unknown")?.write_scalar(&self.unknown)?;
+        metadata::attribute::<u64>(dataset, "offset_back")?.write_scalar(&self.offset_back)?;
+        metadata::attribute::<u64>(dataset, "offset_front")?.write_scalar(&self.offset_front)?;
+        Ok(())
+    }
+}
+
+fn read_counts(hdf5_file: &hdf5::File) -> Result<Counts> {
+    let absolute_dataset = match hdf5_file.dataset("/absolute_data/") {
+        Ok(dataset) => dataset,
+        Err(e) => match hdf5_file.dataset("/results/") {
+            Ok(dataset) => dataset,
+            Err(oe) => {
+                return Err(Error::Schema(format!(
+                    "Could not find the dataset in this file: {e} | {oe}"
+                )))
+            }
+        },
+    };
+    let offset_front = metadata::read_offset(&absolute_dataset, "offset_front")?;
+    let offset_back = metadata::read_offset(&absolute_dataset, "offset_back")?;
+    let acceptable_types = metadata::read_alphabet(&absolute_dataset)?;
+    let unknown = metadata::read_unknown(&absolute_dataset)?;
+
+    let data: Array3<u64> = absolute_dataset.read()?;
+    let size = acceptable_types.len() + unknown as usize;
+    let expected = [size, size, offset_back as usize + offset_front as usize + 1];
+    if data.shape() != expected {
+        return Err(Error::Schema(format!(
+            "absolute_data has the shape {:?} but the attributes describe {:?}",
+            data.shape(),
+            expected
+        )));
+    }
+    Ok(Counts {
+        data,
+        acceptable_types,
+        offset_back,
+        offset_front,
+        unknown,
+    })
+}
+
+fn open_sources(arguments: &[String], mmap: bool) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for input in input::resolve(arguments)? {
+        sources.push(input.open(mmap)?);
+    }
+    Ok(sources)
+}
+
+fn print_progress(done: usize, total: usize) {
+    print!(
+        "At {done} out of {total} = {:.1}%\r",
+        (done as f32 / total as f32) * 100.0
+    );
+}
+
+// The alphabet and how it was chosen, which is stored next to the results
+fn choose_alphabet(
+    alphabet: AlphabetArgs,
+    selection: SelectionArgs,
+    sources: &[Source],
+    frequencies: FrequencyCounter,
+) -> Result<(Vec<char>, String)> {
+    let selection = selection.selection()?;
+    if alphabet.alphabet.contains(&AlphabetChoice::Auto) {
+        if alphabet.alphabet.len() > 1
+            || alphabet.acceptable_types.is_some()
+            || alphabet.alphabet_file.is_some()
+        {
+            return Err(Error::Validation(
+                "--alphabet auto can not be combined with other alphabets".to_string(),
+            ));
+        }
+        let selection = selection.unwrap_or(Selection::All);
+        let alphabet = selection.select(&frequencies.count(sources)?);
+        eprintln!(
+            "Chose {} characters ({selection}): {:?}",
+            alphabet.len(),
+            alphabet.iter().collect::<String>()
+        );
+        return Ok((alphabet, selection.to_string()));
+    }
+    if selection.is_some() {
+        return Err(Error::Validation(
+            "--top, --min-count and --coverage only work with --alphabet auto".to_string(),
+        ));
+    }
+
+    let mut parts = Vec::new();
+    let mut chosen_by = Vec::new();
+    if let Some(acceptable_types) = alphabet.acceptable_types {
+        validate_alphabet(&acceptable_types)?;
+        parts.push(acceptable_types);
+        chosen_by.push("manual".to_string());
+    }
+    for choice in alphabet.alphabet {
+        if let AlphabetChoice::Preset(preset) = choice {
+            parts.push(preset.characters());
+            let name = preset
+                .to_possible_value()
+                .expect("Presets are never skipped");
+            chosen_by.push(name.get_name().to_string());
+        }
+    }
+    if let Some(path) = alphabet.alphabet_file {
+        let characters = alphabet::read_alphabet_file(&path)?;
+        validate_alphabet(&characters)?;
+        parts.push(characters);
+        chosen_by.push(format!("file {}", path.display()));
+    }
+
+    // Presets overlap, so characters already chosen are left out
+    let mut acceptable_types: Vec<char> = Vec::new();
+    for character in parts.into_iter().flatten() {
+        if !acceptable_types.contains(&character) {
+            acceptable_types.push(character);
+        }
+    }
+    Ok((acceptable_types, chosen_by.join(" + ")))
+}
+
+fn validate_alphabet(acceptable_types: &[char]) -> Result<()> {
+    for (index, character) in acceptable_types.iter().enumerate() {
+        if acceptable_types[..index].contains(character) {
+            return Err(Error::Validation(format!(
+                "{character:?} is part of the alphabet more than once"
+            )));
+        }
+    }
+    Ok(())
+}
+
+// <OUTPUT>_<character>.csv, or <OUTPUT>_index_<n>.csv for the n-th character of
+// the alphabet if the character can not be part of a file name
+fn csv_name(output: &str, index: usize, character: char) -> String {
+    let reserved = match cfg!(windows) {
+        true => "/\\:*?\"<>|",
+        false => "/",
+    };
+    match character.is_control() || reserved.contains(character) {
+        true => format!("{output}_index_{index}.csv"),
+        false => format!("{output}_{character}.csv"),
+    }
+}
+
+fn parse_size(size: &str) -> std::result::Result<usize, String> {
+    let size = size.trim_end_matches("iB").trim_end_matches('B');
+    let (number, factor) = match size.char_indices().last() {
+        Some((position, 'K' | 'k')) => (&size[..position], 1 << 10),
+        Some((position, 'M' | 'm')) => (&size[..position], 1 << 20),
+        Some((position, 'G' | 'g')) => (&size[..position], 1 << 30),
+        _ => (size, 1),
+    };
+    let number: usize = number
+        .parse()
+        .map_err(|_| format!("{size} is not a size like 65536, 64K or 4M"))?;
+    number
+        .checked_mul(factor)
+        .ok_or_else(|| format!("{size} is too large"))
+}
+
+fn parse_parameter(parameter: &str) -> std::result::Result<(String, String), String> {
+    match parameter.split_once('=') {
+        Some((name, value)) => Ok((name.to_string(), value.to_string())),
+        None => Err(format!("{parameter:?} is not NAME=VALUE")),
+    }
+}
+
+fn validate_offset(name: &str, offset: isize) -> Result<usize> {
+    usize::try_from(offset)
+        .map_err(|_| Error::Validation(format!("--{name} must not be negative, got {offset}")))
+}
@@ -0,0 +1,139 @@
+use crate::alphabet::{escape, unescape_characters};
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Replaces characters with others before they are looked up in the alphabet,
+/// e.g. every digit with `#`, so that they are counted as one character.
+///
+/// ```
+/// use character_gather::Mapping;
+///
+/// let mapping = Mapping::new().map("0123456789".chars(), '#')?.map(['\t', '\n'], ' ')?;
+/// assert_eq!(mapping.get('7'), '#');
+/// assert_eq!(mapping.get('\n'), ' ');
+/// assert_eq!(mapping.get('a'), 'a');
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mapping {
+    table: BTreeMap<char, char>,
+}
+
+impl Mapping {
+    /// A mapping leaving every character as it is.
+    pub fn new() -> Mapping {
+        Mapping::default()
+    }
+
+    /// Replaces every one of `from` with `to`. A character can only be
+    /// replaced with one other.
+    pub fn map(mut self, from: impl IntoIterator<Item = char>, to: char) -> Result<Mapping> {
+        for character in from {
+            match self.table.insert(character, to) {
+                Some(previous) if previous != to => {
+                    return Err(Error::Validation(format!(
+                        "{character:?} is mapped to both {previous:?} and {to:?}"
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+
+    /// The character `character` is replaced with.
+    pub fn get(&self, character: char) -> char {
+        *self.table.get(&character).unwrap_or(&character)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Number of characters that are replaced.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+}
+
+/// Written in the mapping file format, so the metadata can be read back with
+/// [`parse_mapping`].
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut targets: BTreeMap<char, String> = BTreeMap::new();
+        for (&from, &to) in &self.table {
+            targets.entry(to).or_default().push_str(&escape(from));
+        }
+        for (to, from) in targets {
+            writeln!(f, "{from} {}", escape(to))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a mapping file. Every line holds the characters to replace, a space
+/// and the character to replace them with, written like in alphabet files
+/// (see [`crate::alphabet::parse_alphabet`]) with `\s` for a space. Empty
+/// lines are skipped.
+///
+/// ```
+/// use character_gather::mapping::parse_mapping;
+///
+/// let mapping = parse_mapping("0123456789 #\n\\t\\n \\s\n.,;:!? .\n")?;
+/// assert_eq!(mapping.get('3'), '#');
+/// assert_eq!(mapping.get('\t'), ' ');
+/// assert_eq!(mapping.get('!'), '.');
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+pub fn parse_mapping(text: &str) -> Result<Mapping> {
+    let mut mapping = Mapping::new();
+    for (number, line) in text.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |reason: &str| {
+            Error::Validation(format!(
+                "Line {} of the mapping file: {line:?} {reason}",
+                number + 1
+            ))
+        };
+        let (from, to) = line.split_once(' ').ok_or_else(|| {
+            invalid("has to be the characters to replace, a space and their replacement")
+        })?;
+        let from = unescape_characters(from)
+            .filter(|from| !from.is_empty())
+            .ok_or_else(|| {
+                invalid("does not start with characters or escapes like \\n or \\u{2014}")
+            })?;
+        let to = match unescape_characters(to).as_deref() {
+            Some(&[to]) => to,
+            _ => return Err(invalid("has to end with exactly one character")),
+        };
+        for character in from {
+            match mapping.table.insert(character, to) {
+                Some(previous) if previous != to => {
+                    return Err(invalid(&format!(
+                        "maps {character:?} to {to:?}, an earlier line to {previous:?}"
+                    )))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(mapping)
+}
+
+/// Reads a mapping file, see [`parse_mapping`].
+pub fn read_mapping_file(path: &Path) -> Result<Mapping> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        Error::Io(std::io::Error::new(
+            e.kind(),
+            format!("{}: {e}", path.display()),
+        ))
+    })?;
+    parse_mapping(&text)
+}
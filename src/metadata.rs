@@ -0,0 +1,98 @@
+use crate::error::{Error, Result};
+use hdf5::types::{VarLenAscii, VarLenUnicode};
+use hdf5::{Attribute, Dataset, H5Type};
+use std::ffi::CString;
+
+// 1: acceptable_types stored as VarLenAscii (no schema_version attribute)
+// 2: acceptable_types stored as UTF-8 VarLenUnicode
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// Creates a scalar attribute or opens it if it already exists. An existing
+/// attribute of another type, like the ASCII alphabet of schema version 1, is
+/// replaced.
+pub fn attribute<T: H5Type>(dataset: &Dataset, name: &str) -> Result<Attribute> {
+    if let Ok(attr) = dataset.attr(name) {
+        match attr.dtype()?.is::<T>() {
+            true => return Ok(attr),
+            false => {
+                drop(attr);
+                delete_attribute(dataset, name)?;
+            }
+        }
+    }
+    Ok(dataset.new_attr::<T>().shape(()).create(name)?)
+}
+
+fn delete_attribute(dataset: &Dataset, name: &str) -> Result<()> {
+    let c_name = CString::new(name)
+        .map_err(|_| Error::Validation(format!("{name:?} contains a null character")))?;
+    // The hdf5 crate has no way to delete attributes yet
+    hdf5::sync::sync(|| {
+        hdf5::h5check(unsafe { hdf5_sys::h5a::H5Adelete(dataset.id(), c_name.as_ptr()) })
+    })?;
+    Ok(())
+}
+
+fn required(dataset: &Dataset, name: &str) -> Result<Attribute> {
+    dataset
+        .attr(name)
+        .map_err(|_| Error::Schema(format!("{} has no attribute {name}", dataset.name())))
+}
+
+/// Reads one of the offset attributes written next to the counts.
+pub fn read_offset(dataset: &Dataset, name: &str) -> Result<u64> {
+    Ok(required(dataset, name)?.read_scalar()?)
+}
+
+/// Writes the alphabet as UTF-8 together with the schema version.
+pub fn write_alphabet(dataset: &Dataset, acceptable_types: &[char]) -> Result<()> {
+    let alphabet: VarLenUnicode = acceptable_types
+        .iter()
+        .collect::<String>()
+        .parse()
+        .map_err(|_| Error::Validation("The alphabet contains a null character".to_string()))?;
+    attribute::<VarLenUnicode>(dataset, "acceptable_types")?.write_scalar(&alphabet)?;
+    attribute::<u64>(dataset, "schema_version")?.write_scalar(&SCHEMA_VERSION)?;
+    Ok(())
+}
+
+/// Writes a text attribute.
+pub fn write_string(dataset: &Dataset, name: &str, value: &str) -> Result<()> {
+    let value: VarLenUnicode = value
+        .parse()
+        .map_err(|_| Error::Validation(format!("{name} contains a null character")))?;
+    attribute::<VarLenUnicode>(dataset, name)?.write_scalar(&value)?;
+    Ok(())
+}
+
+/// Whether the counts have an extra row and column for the characters
+/// outside of the alphabet, files written before that never have.
+pub fn read_unknown(dataset: &Dataset) -> Result<bool> {
+    match dataset.attr("unknown") {
+        Ok(attr) => Ok(attr.read_scalar()?),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Reads the alphabet of files of every schema version.
+pub fn read_alphabet(dataset: &Dataset) -> Result<Vec<char>> {
+    let version: u64 = match dataset.attr("schema_version") {
+        Ok(attr) => attr.read_scalar()?,
+        Err(_) => 1,
+    };
+    let attr = required(dataset, "acceptable_types")?;
+    let acceptable_types = match version {
+        1 => attr
+            .read_scalar::<VarLenAscii>()?
+            .as_bytes()
+            .iter()
+            .map(|c| *c as char)
+            .collect(),
+        _ => attr
+            .read_scalar::<VarLenUnicode>()?
+            .as_str()
+            .chars()
+            .collect(),
+    };
+    Ok(acceptable_types)
+}
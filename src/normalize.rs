@@ -1,135 +1,876 @@
-use std::{
-    cmp::{max, min},
-    u64::{MAX, MIN},
-};
-
-use ndarray::{Array1, Array3, Axis};
-
-pub fn normalizer_min_max(array: Array3<u64>) -> Array3<f64> {
-    let dim = array.shape();
-
-    let mut final_sum = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-    for z in 0..dim[2] {
-        let mut sum = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-        for x in 0..dim[0] {
-            let mut maximum: u64 = MIN;
-            let mut minimum: u64 = MAX;
-            for y in 0..dim[1] {
-                // print!("{:?},", array.get((x, y, z)).unwrap());
-                let value = array.get((x, y, z)).unwrap();
-                maximum = max(maximum, *value);
-                minimum = min(minimum, *value);
-            }
+use crate::alphabet::UNKNOWN;
+use crate::error::{Error, Result};
+use clap::ValueEnum;
+use ndarray::Array3;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 
-            let maximum: f64 = maximum as f64;
-            let minimum: f64 = minimum as f64;
-            let difference: f64 = maximum - minimum;
-            for y in 0..dim[1] {
-                let value = array.get((x, y, z)).unwrap();
-                let new = sum.get_mut((x, y, z)).unwrap();
-                *new = (*value as f64 - minimum) / difference;
-            }
+/// Parameters of a normalizer by name, e.g. from `--param name=value`.
+pub type Parameters = BTreeMap<String, String>;
+
+/// The axes of the counts whose values are normalized together. Every
+/// combination of the other axes is a group of its own, e.g. only `found`
+/// normalizes every base character and distance on its own, `found` and
+/// `distance` every base character across all distances.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grouping {
+    pub base: bool,
+    pub found: bool,
+    pub distance: bool,
+}
+
+impl Grouping {
+    /// All found characters of one base character and distance
+    pub const FOUND: Grouping = Grouping {
+        base: false,
+        found: true,
+        distance: false,
+    };
+    /// The whole counts at once
+    pub const ALL: Grouping = Grouping {
+        base: true,
+        found: true,
+        distance: true,
+    };
+
+    fn axes(&self) -> [bool; 3] {
+        [self.base, self.found, self.distance]
+    }
+}
+
+impl Default for Grouping {
+    fn default() -> Grouping {
+        Grouping::FOUND
+    }
+}
+
+impl fmt::Display for Grouping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let axes: Vec<&str> = ["base", "found", "distance"]
+            .into_iter()
+            .zip(self.axes())
+            .filter(|&(_, grouped)| grouped)
+            .map(|(name, _)| name)
+            .collect();
+        write!(f, "{}", axes.join(", "))
+    }
+}
+
+/// Turns the counts into normalized values.
+///
+/// Most normalizations only need [`Normalizer::normalize_group`], which sees
+/// the counts of one group, see [`Grouping`], at once. Normalizations needing
+/// more override [`Normalizer::normalize`].
+///
+/// ```
+/// use character_gather::normalize::{Grouping, Normalizer, Parameters, Registry};
+/// use ndarray::Array3;
+///
+/// struct Log;
+///
+/// impl Normalizer for Log {
+///     fn name(&self) -> &str {
+///         "log"
+///     }
+///
+///     fn normalize_group(&self, values: &mut [f64]) {
+///         values.iter_mut().for_each(|value| *value = value.ln_1p());
+///     }
+/// }
+///
+/// let mut registry = Registry::default();
+/// registry.register("log", "ln(1 + X)", |_: &Parameters| Ok(Box::new(Log)))?;
+/// let log = registry.create("log", &Parameters::new())?;
+/// let normalized = log.normalize(&Array3::from_elem((2, 2, 3), 1), Grouping::FOUND, 1);
+/// assert_eq!(normalized[(1, 0, 2)], 2f64.ln());
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+pub trait Normalizer {
+    /// The name the normalizer is chosen by.
+    fn name(&self) -> &str;
+
+    /// The parameters the normalizer was created with, by name. Stored next to
+    /// the normalized data.
+    fn params(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Whether every group is turned into a distribution that sums to 1, only
+    /// then [`EmptyPolicy::Uniform`] makes sense.
+    fn is_distribution(&self) -> bool {
+        false
+    }
+
+    /// Normalizes the values of one group in place.
+    fn normalize_group(&self, values: &mut [f64]);
+
+    /// Normalizes `counts` of the shape `(base, found, distance)` group by
+    /// group. A character is never counted as its own neighbour, so the
+    /// distance 0 at the index `offset_back` is left out of every group and
+    /// written as 0.
+    fn normalize(
+        &self,
+        counts: &Array3<u64>,
+        grouping: Grouping,
+        offset_back: usize,
+    ) -> Array3<f64> {
+        for_each_group(
+            counts.mapv(|count| count as f64),
+            grouping,
+            offset_back,
+            |_, values| self.normalize_group(values),
+        )
+    }
+}
+
+/// What is written for a group whose counts are all 0, e.g. the counts of a
+/// character that never occurs, or that has no defined result although it was
+/// counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EmptyPolicy {
+    /// 0 for every value of the group
+    Zero,
+    /// 1 / (values in the group) for every value of the group, only for
+    /// normalizers that turn a group into a distribution like sum-one
+    Uniform,
+    /// NaN for every value of the group
+    Nan,
+    /// Stop with a list of the empty and degenerate groups
+    Fail,
+}
+
+impl fmt::Display for EmptyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EmptyPolicy::Zero => "zero",
+            EmptyPolicy::Uniform => "uniform",
+            EmptyPolicy::Nan => "nan",
+            EmptyPolicy::Fail => "fail",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A group of the normalized values, by its index on the axes that are not
+/// grouped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Group {
+    pub base: Option<usize>,
+    pub found: Option<usize>,
+    pub distance: Option<usize>,
+}
+
+impl Group {
+    /// The group with its characters and distance, an index one past the
+    /// alphabet is the unknown character.
+    pub fn describe(&self, alphabet: &[char], offset_back: usize) -> String {
+        let character = |index: usize| match alphabet.get(index) {
+            Some(character) => format!("{character:?}"),
+            None => UNKNOWN.to_string(),
+        };
+        let mut parts = Vec::new();
+        if let Some(base) = self.base {
+            parts.push(format!("base {}", character(base)));
+        }
+        if let Some(found) = self.found {
+            parts.push(format!("found {}", character(found)));
+        }
+        if let Some(distance) = self.distance {
+            parts.push(format!(
+                "distance {}",
+                distance as isize - offset_back as isize
+            ));
         }
-        final_sum += &sum;
+        parts.join(" ")
     }
-    return final_sum;
 }
 
-pub fn normalizer_sum_one(array: Array3<u64>) -> Array3<f64> {
-    let dim = array.shape();
+/// How many groups were normalized, which of them were empty and which were
+/// counted but have no defined result, e.g. min-max of equal counts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub groups: usize,
+    pub empty: Vec<Group>,
+    pub degenerate: Vec<Group>,
+}
+
+impl Summary {
+    /// Lists the empty groups, see [`Group::describe`].
+    pub fn report(&self, alphabet: &[char], offset_back: usize) -> String {
+        describe_all(&self.empty, alphabet, offset_back)
+    }
+
+    /// Lists the degenerate groups, see [`Group::describe`].
+    pub fn report_degenerate(&self, alphabet: &[char], offset_back: usize) -> String {
+        describe_all(&self.degenerate, alphabet, offset_back)
+    }
+}
+
+fn describe_all(groups: &[Group], alphabet: &[char], offset_back: usize) -> String {
+    groups
+        .iter()
+        .map(|group| group.describe(alphabet, offset_back))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Applies `policy` to every group of `normalized` whose `counts` are all 0,
+/// and to every degenerate group, which was counted but has values that are
+/// not finite, e.g. min-max of equal counts. The two are listed apart in the
+/// [`Summary`]. With [`EmptyPolicy::Fail`] the values are left as they are.
+///
+/// Like in [`Normalizer::normalize`] the distance 0 at the index
+/// `offset_back` is left out, so a group of only the distance 0 is written as
+/// 0 and not counted in the [`Summary`].
+pub fn handle_empty(
+    counts: &Array3<u64>,
+    normalized: Array3<f64>,
+    grouping: Grouping,
+    policy: EmptyPolicy,
+    offset_back: usize,
+) -> (Array3<f64>, Summary) {
+    let mut sums = Vec::new();
+    for_each_group(
+        counts.mapv(|count| count as f64),
+        grouping,
+        offset_back,
+        |_, values| sums.push(values.iter().sum::<f64>()),
+    );
+    let mut sums = sums.into_iter();
+
+    let mut summary = Summary::default();
+    let normalized = for_each_group(normalized, grouping, offset_back, |group, values| {
+        let sum = sums.next().expect("Counts and values have the same shape");
+        summary.groups += 1;
+        match sum > 0.0 {
+            true if values.iter().all(|value| value.is_finite()) => return,
+            true => summary.degenerate.push(group),
+            false => summary.empty.push(group),
+        }
+        let fill = match policy {
+            EmptyPolicy::Zero => 0.0,
+            EmptyPolicy::Uniform => 1.0 / values.len() as f64,
+            EmptyPolicy::Nan => f64::NAN,
+            EmptyPolicy::Fail => return,
+        };
+        values.fill(fill);
+    });
+    (normalized, summary)
+}
+
+// Calls `f` with every group of `values` as one slice, without the values at
+// the distance index `centre`, which are set to 0. Groups of only that
+// distance are not passed to `f` at all.
+fn for_each_group(
+    values: Array3<f64>,
+    grouping: Grouping,
+    centre: usize,
+    mut f: impl FnMut(Group, &mut [f64]),
+) -> Array3<f64> {
+    // With the grouped axes moved to the end every group is a contiguous run
+    // of values
+    let grouped = grouping.axes();
+    let mut order: Vec<usize> = (0..3).filter(|&axis| !grouped[axis]).collect();
+    order.extend((0..3).filter(|&axis| grouped[axis]));
+    let shape = values.shape().to_vec();
+    let group_len: usize = (0..3)
+        .filter(|&axis| grouped[axis])
+        .map(|axis| shape[axis])
+        .product();
 
-    let mut final_sum = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-    for z in 0..dim[2] {
-        let mut adder = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-        for x in 0..dim[0] {
-            let mut sum: u64 = 0;
-            for y in 0..dim[1] {
-                sum += *array.get((x, y, z)).unwrap() as u64;
+    let mut permuted = values
+        .permuted_axes([order[0], order[1], order[2]])
+        .as_standard_layout()
+        .into_owned();
+    if group_len > 0 {
+        let values = permuted.as_slice_mut().expect("Standard layout");
+        for (index, group) in values.chunks_mut(group_len).enumerate() {
+            // The index of the group counts through the axes that are not
+            // grouped, the last one fastest
+            let mut coordinates = [None; 3];
+            let mut rest = index;
+            for &axis in order.iter().filter(|&&axis| !grouped[axis]).rev() {
+                coordinates[axis] = Some(rest % shape[axis]);
+                rest /= shape[axis];
+            }
+            let [base, found, distance] = coordinates;
+            let id = Group {
+                base,
+                found,
+                distance,
+            };
+            if distance == Some(centre) {
+                group.fill(0.0);
+                continue;
             }
-            let sum: f64 = sum as f64;
-            for y in 0..dim[1] {
-                let value = array.get((x, y, z)).unwrap();
-                let new = adder.get_mut((x, y, z)).unwrap();
-                *new = *value as f64 / sum;
+            if !grouping.distance || centre >= shape[2] {
+                f(id, group);
+                continue;
+            }
+            // The distance is the last grouped axis, so it counts fastest
+            // through the group
+            let distances = shape[2];
+            let mut kept: Vec<f64> = group
+                .iter()
+                .enumerate()
+                .filter(|&(position, _)| position % distances != centre)
+                .map(|(_, &value)| value)
+                .collect();
+            f(id, &mut kept);
+            let mut kept = kept.into_iter();
+            for (position, value) in group.iter_mut().enumerate() {
+                *value = match position % distances == centre {
+                    true => 0.0,
+                    false => kept.next().expect("One value per kept position"),
+                };
             }
         }
-        final_sum += &adder;
     }
-    return final_sum;
+
+    let mut inverse = [0; 3];
+    for (position, &axis) in order.iter().enumerate() {
+        inverse[axis] = position;
+    }
+    permuted
+        .permuted_axes(inverse)
+        .as_standard_layout()
+        .into_owned()
 }
 
-pub fn normalizer_minus_mean(array: Array3<u64>) -> Array3<f64> {
-    let dim = array.shape();
+type Constructor = Box<dyn Fn(&Parameters) -> Result<Box<dyn Normalizer>>>;
+type Builtin = fn() -> Box<dyn Normalizer>;
 
-    let mut final_sum = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-    for z in 0..dim[2] {
-        let mut adder = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-        for x in 0..dim[0] {
-            let mut sum: u64 = 0;
-            for y in 0..dim[1] {
-                sum += *array.get((x, y, z)).unwrap() as u64;
-            }
-            let durchschnitt: f64 = sum as f64 / dim[1] as f64;
-            for y in 0..dim[1] {
-                let value = array.get((x, y, z)).unwrap();
-                let new = adder.get_mut((x, y, z)).unwrap();
-                *new = *value as f64 - durchschnitt;
-            }
+struct Entry {
+    name: String,
+    description: String,
+    create: Constructor,
+}
+
+/// The normalizers that can be chosen by name. [`Registry::default`] holds
+/// the built in ones, more can be added with [`Registry::register`].
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry::new();
+        let builtin: [(&str, &str, Builtin); 6] = [
+            ("min-max", "(X - min) / (max - min)", || Box::new(MinMax)),
+            ("sum-one", "X / sum", || Box::new(SumOne)),
+            ("minus-mean", "X - mean", || Box::new(MinusMean)),
+            ("divide-max", "X / max", || Box::new(DivideMax)),
+            ("iqr", "(X - median) / interquartile range", || {
+                Box::new(Iqr)
+            }),
+            ("rank", "Percentile of X in its group, from 0 to 1", || {
+                Box::new(Rank)
+            }),
+        ];
+        for (name, description, create) in builtin {
+            registry
+                .register(name, description, move |_: &Parameters| Ok(create()))
+                .expect("Built in normalizers have distinct names");
         }
-        final_sum += &adder;
+        registry
+            .register(
+                "zscore",
+                "(X - mean) / standard deviation, std=population|sample",
+                |params: &Parameters| {
+                    Ok(Box::new(ZScore {
+                        deviation: parameter(params, "std", Deviation::Population)?,
+                    }))
+                },
+            )
+            .expect("Built in normalizers have distinct names");
+        registry
+            .register(
+                "median-mad",
+                "(X - median) / (scale * median absolute deviation), scale=1.4826",
+                |params: &Parameters| {
+                    Ok(Box::new(MedianMad {
+                        scale: parameter(params, "scale", MedianMad::default().scale)?,
+                    }))
+                },
+            )
+            .expect("Built in normalizers have distinct names");
+        registry
     }
-    return final_sum;
 }
 
-pub fn normalizer_divide_max(array: Array3<u64>) -> Array3<f64> {
-    let dim = array.shape();
+impl Registry {
+    /// A registry without any normalizer.
+    pub fn new() -> Registry {
+        Registry {
+            entries: Vec::new(),
+        }
+    }
 
-    let mut final_sum = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-    for z in 0..dim[2] {
-        let mut sum = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-        for x in 0..dim[0] {
-            let mut maximum: u64 = MIN;
-            for y in 0..dim[1] {
-                let value = array.get((x, y, z)).unwrap();
-                maximum = max(maximum, *value);
-            }
+    /// Adds a normalizer, `create` builds it out of the given parameters.
+    pub fn register<F>(&mut self, name: &str, description: &str, create: F) -> Result<()>
+    where
+        F: Fn(&Parameters) -> Result<Box<dyn Normalizer>> + 'static,
+    {
+        if self.entries.iter().any(|entry| entry.name == name) {
+            return Err(Error::Validation(format!(
+                "There already is a normalizer called {name}"
+            )));
+        }
+        self.entries.push(Entry {
+            name: name.to_string(),
+            description: description.to_string(),
+            create: Box::new(create),
+        });
+        Ok(())
+    }
 
-            let maximum: f64 = maximum as f64;
-            for y in 0..dim[1] {
-                let value = array.get((x, y, z)).unwrap();
-                let new = sum.get_mut((x, y, z)).unwrap();
-                *new = *value as f64 / maximum;
-            }
+    /// Creates the normalizer called `name`. Parameters it does not know are
+    /// an error.
+    pub fn create(&self, name: &str, params: &Parameters) -> Result<Box<dyn Normalizer>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| {
+                Error::Validation(format!(
+                    "No normalizer called {name}, choose one of {}",
+                    self.names().collect::<Vec<_>>().join(", ")
+                ))
+            })?;
+        let normalizer = (entry.create)(params)?;
+        let known = normalizer.params();
+        if let Some(unknown) = params
+            .keys()
+            .find(|key| !known.iter().any(|(name, _)| name == *key))
+        {
+            return Err(Error::Validation(format!(
+                "The normalizer {name} has no parameter {unknown}"
+            )));
+        }
+        Ok(normalizer)
+    }
+
+    /// The names of all normalizers in the order they were registered.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    /// The name and description of every normalizer.
+    pub fn list(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.description.as_str()))
+    }
+}
+
+/// Reads the parameter `name` out of `params`, `default` if it is not given.
+pub fn parameter<T: FromStr>(params: &Parameters, name: &str, default: T) -> Result<T> {
+    match params.get(name) {
+        Some(value) => value.parse().map_err(|_| {
+            Error::Validation(format!("Invalid value {value:?} for the parameter {name}"))
+        }),
+        None => Ok(default),
+    }
+}
+
+/// (X - min) / (max - min)
+#[derive(Clone, Copy, Debug)]
+pub struct MinMax;
+
+impl Normalizer for MinMax {
+    fn name(&self) -> &str {
+        "min-max"
+    }
+
+    fn normalize_group(&self, values: &mut [f64]) {
+        let minimum = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let maximum = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let difference = maximum - minimum;
+        for value in values {
+            *value = (*value - minimum) / difference;
+        }
+    }
+}
+
+/// X / sum
+#[derive(Clone, Copy, Debug)]
+pub struct SumOne;
+
+impl Normalizer for SumOne {
+    fn name(&self) -> &str {
+        "sum-one"
+    }
+
+    fn is_distribution(&self) -> bool {
+        true
+    }
+
+    fn normalize_group(&self, values: &mut [f64]) {
+        let sum: f64 = values.iter().sum();
+        for value in values {
+            *value /= sum;
+        }
+    }
+}
+
+/// X - mean
+#[derive(Clone, Copy, Debug)]
+pub struct MinusMean;
+
+impl Normalizer for MinusMean {
+    fn name(&self) -> &str {
+        "minus-mean"
+    }
+
+    fn normalize_group(&self, values: &mut [f64]) {
+        let mean = mean(values);
+        for value in values {
+            *value -= mean;
+        }
+    }
+}
+
+/// X / max
+#[derive(Clone, Copy, Debug)]
+pub struct DivideMax;
+
+impl Normalizer for DivideMax {
+    fn name(&self) -> &str {
+        "divide-max"
+    }
+
+    fn normalize_group(&self, values: &mut [f64]) {
+        let maximum = values.iter().copied().fold(0.0, f64::max);
+        for value in values {
+            *value /= maximum;
+        }
+    }
+}
+
+/// Whether the standard deviation is that of the values themselves or the
+/// estimate for the population they are a sample of.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Deviation {
+    /// Divides the squared deviations by n
+    #[default]
+    Population,
+    /// Divides the squared deviations by n - 1
+    Sample,
+}
+
+impl FromStr for Deviation {
+    type Err = String;
+
+    fn from_str(deviation: &str) -> std::result::Result<Deviation, String> {
+        match deviation {
+            "population" => Ok(Deviation::Population),
+            "sample" => Ok(Deviation::Sample),
+            _ => Err(format!("{deviation} is neither population nor sample")),
+        }
+    }
+}
+
+impl fmt::Display for Deviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deviation::Population => write!(f, "population"),
+            Deviation::Sample => write!(f, "sample"),
+        }
+    }
+}
+
+/// (X - mean) / standard deviation
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ZScore {
+    pub deviation: Deviation,
+}
+
+impl Normalizer for ZScore {
+    fn name(&self) -> &str {
+        "zscore"
+    }
+
+    fn params(&self) -> Vec<(String, String)> {
+        vec![("std".to_string(), self.deviation.to_string())]
+    }
+
+    fn normalize_group(&self, values: &mut [f64]) {
+        let mean = mean(values);
+        let squares: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+        let degrees = match self.deviation {
+            Deviation::Population => values.len() as f64,
+            Deviation::Sample => values.len() as f64 - 1.0,
+        };
+        let deviation = (squares / degrees).sqrt();
+        for value in values {
+            *value = (*value - mean) / deviation;
+        }
+    }
+}
+
+/// (X - median) / (scale * median absolute deviation), the default scale
+/// makes the denominator the standard deviation for normally distributed
+/// values. Counts are mostly 0, so when more than half of a group is the
+/// median the median absolute deviation is estimated from the mean absolute
+/// deviation instead, see [`MAD_PER_MEAN_DEVIATION`].
+#[derive(Clone, Copy, Debug)]
+pub struct MedianMad {
+    pub scale: f64,
+}
+
+impl Default for MedianMad {
+    fn default() -> MedianMad {
+        MedianMad { scale: 1.4826 }
+    }
+}
+
+impl Normalizer for MedianMad {
+    fn name(&self) -> &str {
+        "median-mad"
+    }
+
+    fn params(&self) -> Vec<(String, String)> {
+        vec![("scale".to_string(), self.scale.to_string())]
+    }
+
+    fn normalize_group(&self, values: &mut [f64]) {
+        let median = quantile(values, 0.5);
+        let mad = self.scale * median_deviation(values, median);
+        for value in values {
+            *value = (*value - median) / mad;
         }
-        final_sum += &sum;
     }
-    return final_sum;
 }
 
-pub fn normalizer_z_score(array: Array3<u64>) -> Array3<f64> {
-    let dim = array.shape();
+/// (X - median) / (third quartile - first quartile). Like for
+/// [`MedianMad`], a range of 0 is estimated from the mean absolute deviation
+/// instead, as twice the median absolute deviation.
+#[derive(Clone, Copy, Debug)]
+pub struct Iqr;
+
+impl Normalizer for Iqr {
+    fn name(&self) -> &str {
+        "iqr"
+    }
 
-    let mut final_sum = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-    for z in 0..dim[2] {
-        let mut adder = Array3::<f64>::zeros((dim[0], dim[1], dim[2]));
-        for x in 0..dim[0] {
-            let mut sum: u64 = 0;
-            for y in 0..dim[1] {
-                sum += *array.get((x, y, z)).unwrap() as u64;
+    fn normalize_group(&self, values: &mut [f64]) {
+        let median = quantile(values, 0.5);
+        let range = match quantile(values, 0.75) - quantile(values, 0.25) {
+            range if range > 0.0 => range,
+            _ => 2.0 * MAD_PER_MEAN_DEVIATION * mean_deviation(values, median),
+        };
+        for value in values {
+            *value = (*value - median) / range;
+        }
+    }
+}
+
+/// The percentile of every value in its group, 0 for the smallest and 1 for
+/// the largest, equal values share their mean rank. A single value is in the
+/// middle, 0.5.
+#[derive(Clone, Copy, Debug)]
+pub struct Rank;
+
+impl Normalizer for Rank {
+    fn name(&self) -> &str {
+        "rank"
+    }
+
+    fn normalize_group(&self, values: &mut [f64]) {
+        if values.len() == 1 {
+            values[0] = 0.5;
+            return;
+        }
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+        let last = values.len() as f64 - 1.0;
+        let mut ranks = vec![0.0; values.len()];
+        let mut start = 0;
+        while start < order.len() {
+            let mut end = start + 1;
+            while end < order.len() && values[order[end]] == values[order[start]] {
+                end += 1;
             }
-            let durchschnitt: f64 = sum as f64 / dim[1] as f64;
-            let standart_abweichung: f64 = (((0..dim[1])
-                .map(|y| array.get((x, y, z)).unwrap().pow(2))
-                .sum::<u64>() as f64)
-                / dim[1] as f64)
-                .sqrt();
-            for y in 0..dim[1] {
-                let value = array.get((x, y, z)).unwrap();
-                let new = adder.get_mut((x, y, z)).unwrap();
-                *new = (*value as f64 - durchschnitt) / standart_abweichung;
+            // The ranks start..end of the ties, counted from 0
+            let rank = (start + end - 1) as f64 / 2.0;
+            for &index in &order[start..end] {
+                ranks[index] = rank / last;
             }
+            start = end;
+        }
+        values.copy_from_slice(&ranks);
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// How many times the mean absolute deviation the median absolute deviation
+/// is for normally distributed values, `Φ⁻¹(0.75) / sqrt(2 / π)`.
+pub const MAD_PER_MEAN_DEVIATION: f64 = 0.8453475393951494;
+
+// The median absolute deviation around `median`, estimated from the mean
+// absolute deviation if it is 0
+fn median_deviation(values: &[f64], median: f64) -> f64 {
+    let deviations: Vec<f64> = values.iter().map(|value| (value - median).abs()).collect();
+    match quantile(&deviations, 0.5) {
+        mad if mad > 0.0 => mad,
+        _ => MAD_PER_MEAN_DEVIATION * mean(&deviations),
+    }
+}
+
+fn mean_deviation(values: &[f64], median: f64) -> f64 {
+    mean(
+        &values
+            .iter()
+            .map(|value| (value - median).abs())
+            .collect::<Vec<_>>(),
+    )
+}
+
+// The quantile interpolating linearly between the two closest values, like
+// numpy and R do by default
+fn quantile(values: &[f64], p: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let position = (sorted.len() - 1) as f64 * p;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (position - below as f64) * (sorted[above] - sorted[below])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(normalizer: &dyn Normalizer, values: &[f64]) -> Vec<f64> {
+        let mut values = values.to_vec();
+        normalizer.normalize_group(&mut values);
+        values
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} != {expected:?}");
         }
-        final_sum += &adder;
     }
-    return final_sum;
+
+    #[test]
+    fn zscore_uses_the_deviation_around_the_mean() {
+        // mean 5, squared deviations 9 + 1 + 1 + 9 = 20
+        let values = [2.0, 4.0, 6.0, 8.0];
+        let population = 5f64.sqrt();
+        assert_close(
+            &normalized(&ZScore::default(), &values),
+            &[
+                -3.0 / population,
+                -1.0 / population,
+                1.0 / population,
+                3.0 / population,
+            ],
+        );
+        let sample = (20.0f64 / 3.0).sqrt();
+        let zscore = ZScore {
+            deviation: Deviation::Sample,
+        };
+        assert_close(
+            &normalized(&zscore, &values),
+            &[-3.0 / sample, -1.0 / sample, 1.0 / sample, 3.0 / sample],
+        );
+    }
+
+    #[test]
+    fn median_mad_is_robust_against_outliers() {
+        // median 3, absolute deviations 2 1 0 1 97, their median 1
+        let values = [1.0, 2.0, 3.0, 4.0, 100.0];
+        let unscaled = MedianMad { scale: 1.0 };
+        assert_close(
+            &normalized(&unscaled, &values),
+            &[-2.0, -1.0, 0.0, 1.0, 97.0],
+        );
+        assert_close(
+            &normalized(&MedianMad::default(), &values)[..2],
+            &[-2.0 / 1.4826, -1.0 / 1.4826],
+        );
+    }
+
+    #[test]
+    fn sparse_groups_fall_back_to_the_mean_deviation() {
+        // median 0, the median of the absolute deviations 0 0 5 0 10 is 0 as
+        // well, their mean 3
+        let values = [0.0, 0.0, 5.0, 0.0, 10.0];
+        let unscaled = MedianMad { scale: 1.0 };
+        let mad = 0.8453475393951494 * 3.0;
+        assert_close(
+            &normalized(&unscaled, &values),
+            &[0.0, 0.0, 5.0 / mad, 0.0, 10.0 / mad],
+        );
+        assert_close(
+            &normalized(&MedianMad::default(), &values)[2..3],
+            &[5.0 / (1.4826 * mad)],
+        );
+
+        // Both quartiles of 7 zeros, a 5 and a 10 are 0, the mean absolute
+        // deviation is 15 / 9
+        let mut values = vec![0.0; 7];
+        values.extend([5.0, 10.0]);
+        let range = 2.0 * 0.8453475393951494 * 15.0 / 9.0;
+        assert_close(
+            &normalized(&Iqr, &values)[6..],
+            &[0.0, 5.0 / range, 10.0 / range],
+        );
+
+        // Equal values have no deviation at all
+        assert!(normalized(&unscaled, &[2.0, 2.0])[0].is_nan());
+        assert!(normalized(&Iqr, &[2.0, 2.0])[0].is_nan());
+    }
+
+    #[test]
+    fn iqr_interpolates_the_quartiles() {
+        // sorted 1 2 4 7 10 15, median 5.5, quartiles at 1.25 and 3.75:
+        // 2 + 0.25 * 2 = 2.5 and 7 + 0.75 * 3 = 9.25
+        let values = [7.0, 1.0, 15.0, 2.0, 10.0, 4.0];
+        let range = 9.25 - 2.5;
+        assert_close(
+            &normalized(&Iqr, &values),
+            &[
+                1.5 / range,
+                -4.5 / range,
+                9.5 / range,
+                -3.5 / range,
+                4.5 / range,
+                -1.5 / range,
+            ],
+        );
+    }
+
+    #[test]
+    fn rank_shares_ties() {
+        // ranks from 0: 3 -> 0, both 5 -> (1 + 2) / 2, 9 -> 3
+        assert_close(
+            &normalized(&Rank, &[5.0, 3.0, 9.0, 5.0]),
+            &[0.5, 0.0, 1.0, 0.5],
+        );
+        assert_eq!(normalized(&Rank, &[4.0]), vec![0.5]);
+    }
+
+    #[test]
+    fn parameters_are_checked() {
+        let registry = Registry::default();
+        let params = Parameters::from([("std".to_string(), "sample".to_string())]);
+        let zscore = registry.create("zscore", &params).unwrap();
+        assert_eq!(
+            zscore.params(),
+            vec![("std".to_string(), "sample".to_string())]
+        );
+
+        let params = Parameters::from([("std".to_string(), "n-1".to_string())]);
+        assert!(registry.create("zscore", &params).is_err());
+    }
 }
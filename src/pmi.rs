@@ -0,0 +1,148 @@
+use crate::error::{Error, Result};
+use ndarray::{Array2, Array3, Axis};
+
+/// How strongly the characters are associated with each other, every distance
+/// on its own. With `n` the counts of a distance,
+///
+/// * `pmi = log2(P(base, found) / (P(base) * P(found)))`
+/// * `ppmi = max(0, log2(P(base, found) / (P(base) * P_alpha(found))))` with
+///   the found characters smoothed to `P_alpha(found) = n(found)^alpha / sum(n(f)^alpha)`
+/// * `npmi = pmi / -log2(P(base, found))`, from -1 to 1
+///
+/// Pairs that were never found have a pmi of -inf, a ppmi of 0 and an npmi
+/// of -1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Association {
+    pub pmi: Array3<f64>,
+    pub ppmi: Array3<f64>,
+    pub npmi: Array3<f64>,
+    /// P(base) of every base character and distance, `(base, distance)`
+    pub base: Array2<f64>,
+    /// P(found) of every found character and distance, `(found, distance)`
+    pub found: Array2<f64>,
+}
+
+/// Computes the [`Association`] out of counts of the shape
+/// `(base, found, distance)`. `alpha` is 1 for no smoothing, 0.75 is common.
+///
+/// ```
+/// use character_gather::pmi::association;
+/// use ndarray::Array3;
+///
+/// // a is found next to b twice as often as next to a
+/// let counts = Array3::from_shape_vec((2, 2, 1), vec![1, 2, 2, 0]).unwrap();
+/// let association = association(&counts, 1.0)?;
+/// assert!(association.pmi[(0, 1, 0)] > 0.0);
+/// assert_eq!(association.ppmi[(0, 0, 0)], 0.0);
+/// assert_eq!(association.npmi[(1, 1, 0)], -1.0);
+/// # Ok::<(), character_gather::Error>(())
+/// ```
+pub fn association(counts: &Array3<u64>, alpha: f64) -> Result<Association> {
+    if !(alpha > 0.0 && alpha.is_finite()) {
+        return Err(Error::Validation(format!(
+            "The smoothing exponent alpha has to be above 0, got {alpha}"
+        )));
+    }
+    let (bases, founds, distances) = counts.dim();
+    let mut association = Association {
+        pmi: Array3::zeros(counts.dim()),
+        ppmi: Array3::zeros(counts.dim()),
+        npmi: Array3::zeros(counts.dim()),
+        base: Array2::zeros((bases, distances)),
+        found: Array2::zeros((founds, distances)),
+    };
+
+    for d in 0..distances {
+        let slice = counts.index_axis(Axis(2), d).mapv(|c| c as f64);
+        let total = slice.sum();
+        if total == 0.0 {
+            association
+                .pmi
+                .index_axis_mut(Axis(2), d)
+                .fill(f64::NEG_INFINITY);
+            association.npmi.index_axis_mut(Axis(2), d).fill(-1.0);
+            continue;
+        }
+        let base = slice.sum_axis(Axis(1)) / total;
+        let found = slice.sum_axis(Axis(0)) / total;
+        let smoothed = slice.sum_axis(Axis(0)).mapv(|n| n.powf(alpha));
+        let smoothed = &smoothed / smoothed.sum();
+        association.base.index_axis_mut(Axis(1), d).assign(&base);
+        association.found.index_axis_mut(Axis(1), d).assign(&found);
+
+        for ((b, f), &count) in slice.indexed_iter() {
+            let index = (b, f, d);
+            if count == 0.0 {
+                association.pmi[index] = f64::NEG_INFINITY;
+                association.ppmi[index] = 0.0;
+                association.npmi[index] = -1.0;
+                continue;
+            }
+            let joint = count / total;
+            let pmi = (joint / (base[b] * found[f])).log2();
+            association.pmi[index] = pmi;
+            association.ppmi[index] = (joint / (base[b] * smoothed[f])).log2().max(0.0);
+            // A pair that is all there is to the distance is as associated as
+            // it gets, although -log2(1) is 0
+            association.npmi[index] = match joint < 1.0 {
+                true => pmi / -joint.log2(),
+                false => 1.0,
+            };
+        }
+    }
+    Ok(association)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn association_matches_hand_computed_values() {
+        // Distance 0: [[2, 1], [1, 0]], n = 4, P(a) = P(b) = 3/4 and 1/4
+        // Distance 1: nothing was found
+        let counts = Array3::from_shape_vec((2, 2, 2), vec![2, 0, 1, 0, 1, 0, 0, 0]).unwrap();
+        let association = association(&counts, 0.75).unwrap();
+
+        assert_eq!(association.base.column(0).to_vec(), vec![0.75, 0.25]);
+        assert_eq!(association.found.column(0).to_vec(), vec![0.75, 0.25]);
+        // log2((2/4) / (3/4 * 3/4)) and log2((1/4) / (3/4 * 1/4))
+        assert_close(association.pmi[(0, 0, 0)], (8.0f64 / 9.0).log2());
+        assert_close(association.pmi[(0, 1, 0)], (4.0f64 / 3.0).log2());
+        assert_eq!(association.pmi[(1, 1, 0)], f64::NEG_INFINITY);
+        // -log2(2/4) = 1 and -log2(1/4) = 2
+        assert_close(association.npmi[(0, 0, 0)], (8.0f64 / 9.0).log2());
+        assert_close(association.npmi[(0, 1, 0)], (4.0f64 / 3.0).log2() / 2.0);
+        assert_eq!(association.npmi[(1, 1, 0)], -1.0);
+
+        // P_0.75(b) = 1 / (3^0.75 + 1)
+        let smoothed = 1.0 / (3f64.powf(0.75) + 1.0);
+        assert_close(
+            association.ppmi[(0, 1, 0)],
+            (0.25 / (0.75 * smoothed)).log2(),
+        );
+        assert_eq!(association.ppmi[(0, 0, 0)], 0.0);
+        assert_eq!(association.ppmi[(1, 1, 0)], 0.0);
+
+        assert_eq!(association.pmi[(0, 1, 1)], f64::NEG_INFINITY);
+        assert_eq!(association.ppmi[(0, 1, 1)], 0.0);
+        assert_eq!(association.npmi[(0, 1, 1)], -1.0);
+        assert_eq!(association.base.column(1).to_vec(), vec![0.0, 0.0]);
+
+        assert!(super::association(&counts, 0.0).is_err());
+    }
+
+    #[test]
+    fn smoothing_of_one_is_pmi() {
+        let counts = Array3::from_shape_vec((2, 2, 1), vec![5, 1, 2, 7]).unwrap();
+        let association = association(&counts, 1.0).unwrap();
+        assert_eq!(association.ppmi, association.pmi.mapv(|pmi| pmi.max(0.0)));
+        // The only pair of a distance
+        let single = super::association(&Array3::from_elem((1, 1, 1), 3), 1.0).unwrap();
+        assert_eq!(single.npmi[(0, 0, 0)], 1.0);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::mapping::Mapping;
+use clap::ValueEnum;
+use std::fmt;
+use std::ops::Range;
+use unicode_normalization::char::{canonical_combining_class, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Form {
+    /// Canonical composition, precomposed and combining ä become the same
+    Nfc,
+    /// Compatibility composition, additionally turns e.g. ﬁ into fi and ² into 2
+    Nfkc,
+}
+
+/// What is done to the text after decoding and before anything is counted.
+///
+/// The steps run in a fixed order: decomposition, diacritic stripping,
+/// lowercase folding, composition into `form`, the `mapping` and whitespace
+/// collapsing.
+///
+/// ```
+/// use character_gather::preprocess::{Form, Preprocessing};
+///
+/// let preprocessing = Preprocessing {
+///     lowercase: true,
+///     form: Some(Form::Nfc),
+///     strip_diacritics: true,
+///     collapse_whitespace: true,
+///     ..Preprocessing::default()
+/// };
+/// let text: Vec<char> = "Ärger  \t über\u{301}".chars().collect();
+/// assert_eq!(preprocessing.process(&text), "arger uber".chars().collect::<Vec<_>>());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preprocessing {
+    /// Replace every character with its lowercase form
+    pub lowercase: bool,
+    /// Bring the text into this Unicode normalization form
+    pub form: Option<Form>,
+    /// Remove combining marks after decomposing, ä becomes a
+    pub strip_diacritics: bool,
+    /// Replace every run of whitespace with a single space
+    pub collapse_whitespace: bool,
+    /// Replace characters with others, e.g. every digit with #
+    pub mapping: Mapping,
+}
+
+impl Preprocessing {
+    /// Whether the text is left as it is.
+    pub fn is_identity(&self) -> bool {
+        *self == Preprocessing::default()
+    }
+
+    /// Whether the only step is the mapping, which never changes how many
+    /// characters there are.
+    pub(crate) fn only_maps(&self) -> bool {
+        Preprocessing {
+            mapping: Mapping::new(),
+            ..self.clone()
+        }
+        .is_identity()
+    }
+
+    /// Preprocesses a whole text.
+    pub fn process(&self, characters: &[char]) -> Vec<char> {
+        let (processed, _) = self
+            .apply(characters, 0..characters.len(), true, true, 0, 0)
+            .expect("A whole text needs no more characters");
+        processed
+    }
+
+    /// Preprocesses the part of a text a chunk reads. `centres` are the
+    /// characters the chunk owns, `at_start` and `at_end` tell whether
+    /// `characters` reach the borders of the text.
+    ///
+    /// Every processed character belongs to the character its segment (a base
+    /// character and the combining marks following it) starts with, so the
+    /// processed centres are those whose segment starts in `centres`. Around
+    /// them at most `offset_back` and `offset_front` processed characters are
+    /// kept. Returns `None` if `characters` do not reach far enough around the
+    /// centres for that.
+    pub(crate) fn apply(
+        &self,
+        characters: &[char],
+        centres: Range<usize>,
+        at_start: bool,
+        at_end: bool,
+        offset_back: usize,
+        offset_front: usize,
+    ) -> Option<(Vec<char>, Range<usize>)> {
+        let mut starts: Vec<usize> = (0..characters.len())
+            .filter(|&i| starts_segment(characters[i]))
+            .collect();
+        if at_start && starts.first() != Some(&0) && !characters.is_empty() {
+            starts.insert(0, 0);
+        }
+        // Without the text in front, the characters before the first start
+        // are the end of an unknown segment and the first segment is only
+        // read to know whether its whitespace continues.
+        if !at_start && starts.first().is_none_or(|&first| first >= centres.start) {
+            return None;
+        }
+        // Without the text behind, the last segment could still go on
+        let complete = match at_end {
+            true => starts.len(),
+            false => match starts.last() {
+                Some(&last) if last >= centres.end => starts.len() - 1,
+                _ => return None,
+            },
+        };
+
+        // (character, index of the character its segment starts with)
+        let mut processed: Vec<(char, usize)> = Vec::new();
+        let mut segment = Vec::new();
+        let mut whitespace = false;
+        for k in 0..complete {
+            let end = starts.get(k + 1).copied().unwrap_or(characters.len());
+            segment.clear();
+            self.process_segment(&characters[starts[k]..end], &mut segment);
+            for &character in &segment {
+                let character = self.mapping.get(character);
+                if self.collapse_whitespace && character.is_whitespace() {
+                    if whitespace {
+                        continue;
+                    }
+                    whitespace = true;
+                    if k > 0 || at_start {
+                        processed.push((' ', starts[k]));
+                    }
+                } else {
+                    whitespace = false;
+                    if k > 0 || at_start {
+                        processed.push((character, starts[k]));
+                    }
+                }
+            }
+        }
+
+        let first = processed.partition_point(|&(_, source)| source < centres.start);
+        let last = processed.partition_point(|&(_, source)| source < centres.end);
+        if (!at_start && first < offset_back) || (!at_end && processed.len() - last < offset_front)
+        {
+            return None;
+        }
+        let halo_start = first.saturating_sub(offset_back);
+        let halo_end = (last + offset_front).min(processed.len());
+        let characters = processed[halo_start..halo_end]
+            .iter()
+            .map(|&(character, _)| character)
+            .collect();
+        Some((characters, first - halo_start..last - halo_start))
+    }
+
+    fn process_segment(&self, segment: &[char], processed: &mut Vec<char>) {
+        let decomposed = self.strip_diacritics || self.form.is_some();
+        let mut characters: Vec<char> = match (self.form, decomposed) {
+            (Some(Form::Nfkc), _) => segment.iter().copied().nfkd().collect(),
+            (_, true) => segment.iter().copied().nfd().collect(),
+            (_, false) => segment.to_vec(),
+        };
+        if self.strip_diacritics {
+            characters.retain(|&c| !is_combining_mark(c));
+        }
+        if self.lowercase {
+            characters = characters
+                .into_iter()
+                .flat_map(char::to_lowercase)
+                .collect();
+        }
+        match (self.form, decomposed) {
+            (Some(Form::Nfkc), _) => processed.extend(characters.into_iter().nfkc()),
+            (_, true) => processed.extend(characters.into_iter().nfc()),
+            (_, false) => processed.extend(characters),
+        }
+    }
+}
+
+impl fmt::Display for Preprocessing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut steps = Vec::new();
+        if let Some(form) = self.form {
+            steps.push(match form {
+                Form::Nfc => "nfc",
+                Form::Nfkc => "nfkc",
+            });
+        }
+        if self.strip_diacritics {
+            steps.push("strip-diacritics");
+        }
+        if self.lowercase {
+            steps.push("lowercase");
+        }
+        let mapping = format!("mapping of {} characters", self.mapping.len());
+        if !self.mapping.is_empty() {
+            steps.push(&mapping);
+        }
+        if self.collapse_whitespace {
+            steps.push("collapse-whitespace");
+        }
+        match steps.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", steps.join(", ")),
+        }
+    }
+}
+
+// Normalization never reaches over the start of a base character, apart from
+// combining marks and the vowels and final consonants of Hangul syllables
+fn starts_segment(character: char) -> bool {
+    canonical_combining_class(character) == 0
+        && !is_combining_mark(character)
+        && !matches!(character, '\u{1160}'..='\u{11ff}')
+}
@@ -0,0 +1,78 @@
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+
+/// The bytes of one document, readable from any number of threads at once.
+pub enum Source {
+    /// Reads with an explicit position instead of seeking, so any number of
+    /// threads can read from the same file at the same time.
+    Positional { file: File, size: usize },
+    /// The whole file is mapped into memory and chunks borrow from the map
+    /// without any copy.
+    Mapped(Mmap),
+    /// Text that is already in memory.
+    Memory(Vec<u8>),
+}
+
+impl Source {
+    /// Reads `file` with positional reads, or memory maps it if `mmap` is set.
+    pub fn new(file: File, mmap: bool) -> io::Result<Source> {
+        if mmap {
+            // The map is only valid as long as nobody truncates or rewrites the
+            // input while we are reading it, the same is assumed for positional reads.
+            let map = unsafe { Mmap::map(&file)? };
+            return Ok(Source::Mapped(map));
+        }
+        let size = file.metadata()?.len() as usize;
+        Ok(Source::Positional { file, size })
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Source::Positional { size, .. } => *size,
+            Source::Mapped(map) => map.len(),
+            Source::Memory(buffer) => buffer.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn read(&self, range: Range<usize>) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            Source::Positional { file, .. } => {
+                let mut buffer = vec![0; range.len()];
+                read_exact_at(file, &mut buffer, range.start as u64)?;
+                Ok(Cow::Owned(buffer))
+            }
+            Source::Mapped(map) => Ok(Cow::Borrowed(&map[range])),
+            Source::Memory(buffer) => Ok(Cow::Borrowed(&buffer[range])),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buffer.is_empty() {
+        match file.seek_read(buffer, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buffer = &mut buffer[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
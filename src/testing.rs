@@ -0,0 +1,25 @@
+// Random text with multibyte characters and invalid UTF-8 for the tests
+pub fn random_text(len: usize, seed: u64) -> Vec<u8> {
+    random_text_from(len, seed, "abcdefgh .\näßд語😀")
+}
+
+pub fn random_text_from(len: usize, seed: u64, symbols: &str) -> Vec<u8> {
+    let symbols: Vec<char> = symbols.chars().collect();
+    let mut state = seed;
+    let mut text = Vec::new();
+    for _ in 0..len {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        match state % 50 {
+            0 => text.push(0xff),
+            1 => text.push(0x80),
+            2 => text.extend(&"語".as_bytes()[..2]),
+            _ => {
+                let symbol = symbols[(state % symbols.len() as u64) as usize];
+                text.extend(symbol.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        }
+    }
+    text
+}
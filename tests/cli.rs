@@ -0,0 +1,40 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn csv_files_are_named_by_character() {
+    let directory = tempfile::tempdir().unwrap();
+    let input = directory.path().join("input.txt");
+    fs::write(&input, "a/b.c/d\\e").unwrap();
+    let output = directory.path().join("windows");
+    let status = Command::new(env!("CARGO_BIN_EXE_character_gather"))
+        .args([
+            "char-dataset",
+            "--alphabet",
+            "punctuation",
+            "-a",
+            "a,b,c,d,e",
+        ])
+        .args(["--offset-back", "1", "--offset-front", "1", "-t", "1", "-i"])
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let alphabet = fs::read_to_string(directory.path().join("windows_alphabet.txt")).unwrap();
+    let alphabet = character_gather::alphabet::parse_alphabet(&alphabet).unwrap();
+    let read = |name: &str| fs::read_to_string(directory.path().join(name)).unwrap();
+    // / can not be part of a file name, so its file is named by its index
+    let slash = alphabet.iter().position(|&c| c == '/').unwrap();
+    assert_eq!(
+        read(&format!("windows_index_{slash}.csv")),
+        "a,/,b,\nc,/,d,\n"
+    );
+    assert_eq!(read("windows_..csv"), "b,.,c,\n");
+    assert_eq!(read("windows_a.csv"), "");
+    if !cfg!(windows) {
+        assert_eq!(read("windows_\\.csv"), "d,\\,e,\n");
+    }
+}
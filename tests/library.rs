@@ -0,0 +1,429 @@
+use character_gather::alphabet::{parse_alphabet, Preset};
+use character_gather::mapping::parse_mapping;
+use character_gather::metadata;
+use character_gather::normalize::{
+    handle_empty, EmptyPolicy, Group, Grouping, MinMax, Normalizer, Parameters, Registry, SumOne,
+};
+use character_gather::{
+    CooccurrenceCounter, Decoding, Encoding, Error, FrequencyCounter, InvalidBytes, Preprocessing,
+    ReadOptions, Selection, Source, WindowExtractor,
+};
+use hdf5::types::VarLenAscii;
+use ndarray::{Array3, Axis};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn strict_decoding_reports_position() {
+    let result = CooccurrenceCounter::new(vec!['a'])
+        .read_options(
+            ReadOptions::new().decoding(Decoding::new(Encoding::Utf8, InvalidBytes::Strict)),
+        )
+        .count(&[Source::Memory(b"aa\xffa".to_vec())]);
+    assert!(matches!(result, Err(Error::Decode { position: 2 })));
+}
+
+#[test]
+fn counter_counts_neighbours() {
+    let counts = CooccurrenceCounter::new(vec!['a', 'b'])
+        .offset_back(1)
+        .offset_front(2)
+        .read_options(ReadOptions::new().threads(2))
+        .count_text("abba")
+        .unwrap();
+
+    let mut expected = Array3::<u64>::zeros((2, 2, 4));
+    // a at 0: b at +1, b at +2
+    expected[(0, 1, 2)] += 1;
+    expected[(0, 1, 3)] += 1;
+    // b at 1: a at -1, b at +1, a at +2
+    expected[(1, 0, 0)] += 1;
+    expected[(1, 1, 2)] += 1;
+    expected[(1, 0, 3)] += 1;
+    // b at 2: b at -1, a at +1
+    expected[(1, 1, 0)] += 1;
+    expected[(1, 0, 2)] += 1;
+    // a at 3: b at -1
+    expected[(0, 1, 0)] += 1;
+    assert_eq!(counts, expected);
+}
+
+#[test]
+fn counter_keeps_sources_apart() {
+    let counter = CooccurrenceCounter::new(vec!['x', 'y'])
+        .offset_back(1)
+        .offset_front(1);
+    let together = counter
+        .count(&[
+            Source::Memory(b"xy".to_vec()),
+            Source::Memory(b"yx".to_vec()),
+        ])
+        .unwrap();
+    assert_eq!(
+        together,
+        counter.count_text("xy").unwrap() + counter.count_text("yx").unwrap()
+    );
+}
+
+#[test]
+fn counter_decodes_utf8() {
+    let alphabet: Vec<char> = "äö".chars().collect();
+    let utf8 = CooccurrenceCounter::new(alphabet.clone())
+        .offset_back(0)
+        .offset_front(1)
+        .count_text("äö")
+        .unwrap();
+    assert_eq!(utf8[(0, 1, 1)], 1);
+
+    let latin1 = CooccurrenceCounter::new(alphabet)
+        .offset_back(0)
+        .offset_front(1)
+        .read_options(
+            ReadOptions::new().decoding(Decoding::new(Encoding::Latin1, InvalidBytes::Strict)),
+        )
+        .count_text("äö")
+        .unwrap();
+    assert_eq!(latin1.sum(), 0);
+}
+
+#[test]
+fn progress_is_reported_per_chunk() {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let seen = reports.clone();
+    CooccurrenceCounter::new(vec!['a'])
+        .offset_back(1)
+        .offset_front(1)
+        .read_options(ReadOptions::new().chunksize(12))
+        .progress(move |done, total| seen.lock().unwrap().push((done, total)))
+        .count_text(&"a".repeat(30))
+        .unwrap();
+    let mut reports = reports.lock().unwrap().clone();
+    reports.sort();
+    assert_eq!(reports, vec![(1, 3), (2, 3), (3, 3)]);
+}
+
+#[test]
+fn normalizer_sums_to_one() {
+    let counts = CooccurrenceCounter::new(vec!['a', 'b', 'c'])
+        .offset_back(2)
+        .offset_front(2)
+        .count_text("abcabcaabbcc")
+        .unwrap();
+    let normalized = SumOne.normalize(&counts, Grouping::FOUND, 2);
+    for base in 0..3 {
+        for distance in [0, 1, 3, 4] {
+            let sum: f64 = (0..3)
+                .map(|found| normalized[(base, found, distance)])
+                .sum();
+            assert!((sum - 1.0).abs() < 1e-12);
+        }
+    }
+}
+
+#[test]
+fn extractor_finds_windows() {
+    let extractor = WindowExtractor::new(vec!['a', 'b', 'c'])
+        .offset_back(1)
+        .offset_front(1);
+    let windows = extractor
+        .windows('b', &[Source::Memory(b"abcxbcbb".to_vec())])
+        .unwrap();
+    assert_eq!(windows, vec![vec!['a', 'b', 'c'], vec!['c', 'b', 'b']]);
+}
+
+#[test]
+fn alphabet_is_chosen_by_frequency() {
+    // a: 5, b: 3, c: 1, ä: 1
+    let frequencies = FrequencyCounter::new()
+        .read_options(ReadOptions::new().chunksize(4))
+        .count(&[Source::Memory("abababacaä".as_bytes().to_vec())])
+        .unwrap();
+    assert_eq!(frequencies, vec![('a', 5), ('b', 3), ('c', 1), ('ä', 1)]);
+
+    assert_eq!(
+        Selection::All.select(&frequencies),
+        vec!['a', 'b', 'c', 'ä']
+    );
+    assert_eq!(Selection::Top(1).select(&frequencies), vec!['a']);
+    assert_eq!(Selection::MinCount(3).select(&frequencies), vec!['a', 'b']);
+    assert_eq!(Selection::Coverage(50.0).select(&frequencies), vec!['a']);
+    assert_eq!(
+        Selection::Coverage(80.0).select(&frequencies),
+        vec!['a', 'b']
+    );
+    assert_eq!(
+        Selection::Coverage(81.0).select(&frequencies),
+        vec!['a', 'b', 'c']
+    );
+}
+
+#[test]
+fn alphabet_file_is_parsed() {
+    let characters = parse_alphabet("a\r\n\\t\r\n\r\n\\\\\r\n\\u{2014}\r\n \r\n").unwrap();
+    assert_eq!(characters, vec!['a', '\t', '\\', '—', ' ']);
+
+    let error = parse_alphabet("a\nb\n\\u{110000}\n").unwrap_err();
+    assert!(error.to_string().contains("Line 3"), "{error}");
+
+    assert_eq!(Preset::German.characters().len(), 30);
+    assert_eq!(Preset::Punctuation.characters().len(), 32);
+}
+
+#[test]
+fn mapping_merges_characters_before_counting() {
+    let mapping = parse_mapping("0123456789 #\n\\t\\n \\s\n").unwrap();
+    assert_eq!(parse_mapping(&mapping.to_string()).unwrap(), mapping);
+    let error = parse_mapping("ab x\nb y\n").unwrap_err();
+    assert!(error.to_string().contains("Line 2"), "{error}");
+
+    let preprocessing = Preprocessing {
+        mapping,
+        ..Preprocessing::default()
+    };
+    let counts = CooccurrenceCounter::new(vec!['#', ' '])
+        .offset_back(0)
+        .offset_front(1)
+        .read_options(ReadOptions::new().preprocessing(preprocessing))
+        .count_text("12\t7")
+        .unwrap();
+    // "12\t7" is counted as "## #"
+    assert_eq!(counts[(0, 0, 1)], 1);
+    assert_eq!(counts[(0, 1, 1)], 1);
+    assert_eq!(counts[(1, 0, 1)], 1);
+}
+
+#[test]
+fn normalizers_are_chosen_by_name() {
+    let registry = Registry::default();
+    // Nothing at distance 0, 1 to 4 at distance 1
+    let counts = Array3::from_shape_vec((1, 4, 2), vec![0, 1, 0, 2, 0, 3, 0, 4]).unwrap();
+    let min_max = registry.create("min-max", &Parameters::new()).unwrap();
+    assert_eq!(min_max.name(), "min-max");
+    assert_eq!(
+        min_max
+            .normalize(&counts, Grouping::FOUND, 0)
+            .into_raw_vec(),
+        vec![0.0, 0.0, 0.0, 1.0 / 3.0, 0.0, 2.0 / 3.0, 0.0, 1.0]
+    );
+
+    let error = registry
+        .create("z-score", &Parameters::new())
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("zscore"), "{error}");
+    let params = Parameters::from([("ddof".to_string(), "1".to_string())]);
+    assert!(registry.create("sum-one", &params).is_err());
+}
+
+#[test]
+fn normalization_groups_follow_the_axes() {
+    // counts[(base, found, distance)] = 1..=12 at the distances -1 and 1,
+    // nothing at distance 0
+    let counts = Array3::from_shape_fn((2, 3, 3), |(base, found, distance)| match distance {
+        1 => 0,
+        _ => 1 + base as u64 * 6 + found as u64 * 2 + distance as u64 / 2,
+    });
+    let grouping = |base, found, distance| Grouping {
+        base,
+        found,
+        distance,
+    };
+    let grouped =
+        |base, found, distance| SumOne.normalize(&counts, grouping(base, found, distance), 1);
+
+    // Every base character across all distances
+    let per_base = grouped(false, true, true);
+    assert_eq!(per_base[(0, 0, 0)], 1.0 / 21.0);
+    assert_eq!(per_base[(1, 2, 2)], 12.0 / 57.0);
+    assert_eq!(per_base[(1, 2, 1)], 0.0);
+    // Every distance across the whole matrix
+    let per_distance = grouped(true, true, false);
+    assert_eq!(per_distance[(0, 0, 2)], 2.0 / 42.0);
+    assert_eq!(per_distance[(1, 1, 0)], 9.0 / 36.0);
+    assert_eq!(per_distance[(1, 1, 1)], 0.0);
+    // Every pair of characters across the distances
+    let per_pair = grouped(false, false, true);
+    assert_eq!(per_pair[(1, 2, 0)], 11.0 / 23.0);
+
+    let all = SumOne.normalize(&counts, Grouping::ALL, 1);
+    assert_eq!(all[(1, 0, 2)], 8.0 / 78.0);
+    assert!((all.sum() - 1.0).abs() < 1e-12);
+
+    // The zeros at distance 0 are not part of the groups, 1 stays the
+    // minimum of base a
+    let min_max = MinMax.normalize(&counts, grouping(false, true, true), 1);
+    assert_eq!(min_max[(0, 0, 0)], 0.0);
+    assert_eq!(min_max[(0, 2, 2)], 1.0);
+    assert_eq!(min_max[(0, 1, 2)], 0.6);
+    assert_eq!(min_max[(0, 1, 1)], 0.0);
+}
+
+#[test]
+fn empty_groups_follow_the_policy() {
+    // 'b' is never found in front of anything, nothing is found at distance 0
+    let counts =
+        Array3::from_shape_vec((2, 2, 3), vec![1, 0, 2, 3, 0, 2, 0, 0, 0, 0, 0, 0]).unwrap();
+    let normalized = SumOne.normalize(&counts, Grouping::FOUND, 1);
+
+    let (zero, summary) = handle_empty(
+        &counts,
+        normalized.clone(),
+        Grouping::FOUND,
+        EmptyPolicy::Zero,
+        1,
+    );
+    assert_eq!(summary.groups, 4);
+    assert_eq!(
+        summary.empty,
+        vec![
+            Group {
+                base: Some(1),
+                found: None,
+                distance: Some(0)
+            },
+            Group {
+                base: Some(1),
+                found: None,
+                distance: Some(2)
+            }
+        ]
+    );
+    assert!(summary.degenerate.is_empty());
+    assert_eq!(
+        summary.report(&['a', 'b'], 1),
+        "base 'b' distance -1, base 'b' distance 1"
+    );
+    assert_eq!(zero[(0, 0, 0)], 0.25);
+    assert_eq!(zero[(0, 0, 2)], 0.5);
+    assert_eq!(zero[(1, 0, 2)], 0.0);
+    // Distance 0 is left out of the summary and written as 0 by every policy
+    assert_eq!(zero[(0, 0, 1)], 0.0);
+
+    let (uniform, _) = handle_empty(
+        &counts,
+        normalized.clone(),
+        Grouping::FOUND,
+        EmptyPolicy::Uniform,
+        1,
+    );
+    assert_eq!(uniform[(1, 1, 0)], 0.5);
+    assert_eq!(uniform[(1, 1, 1)], 0.0);
+    let (nan, _) = handle_empty(&counts, normalized, Grouping::FOUND, EmptyPolicy::Nan, 1);
+    assert!(nan[(1, 1, 0)].is_nan());
+    assert_eq!(nan[(0, 1, 1)], 0.0);
+
+    let (_, summary) = handle_empty(
+        &counts,
+        SumOne.normalize(&counts, Grouping::ALL, 1),
+        Grouping::ALL,
+        EmptyPolicy::Fail,
+        1,
+    );
+    assert_eq!(summary.groups, 1);
+    assert!(summary.empty.is_empty());
+
+    // Equal counts were found, min-max has no result for them but they are
+    // not empty
+    let (min_max, summary) = handle_empty(
+        &counts,
+        MinMax.normalize(&counts, Grouping::FOUND, 1),
+        Grouping::FOUND,
+        EmptyPolicy::Zero,
+        1,
+    );
+    assert_eq!(summary.empty.len(), 2);
+    assert_eq!(
+        summary.report_degenerate(&['a', 'b'], 1),
+        "base 'a' distance 1"
+    );
+    // The policy applies to them like to the empty groups
+    assert_eq!(min_max[(0, 0, 2)], 0.0);
+    assert_eq!(min_max[(0, 1, 2)], 0.0);
+    assert_eq!(min_max[(1, 0, 2)], 0.0);
+    assert_eq!(min_max[(0, 0, 0)], 0.0);
+    assert_eq!(min_max[(0, 1, 0)], 1.0);
+    let (min_max, summary) = handle_empty(
+        &counts,
+        MinMax.normalize(&counts, Grouping::FOUND, 1),
+        Grouping::FOUND,
+        EmptyPolicy::Nan,
+        1,
+    );
+    assert_eq!(summary.degenerate.len(), 1);
+    assert!(min_max[(0, 1, 2)].is_nan());
+    assert_eq!(min_max[(0, 1, 0)], 1.0);
+    assert!(!MinMax.is_distribution());
+    assert!(SumOne.is_distribution());
+}
+
+#[test]
+fn counted_text_has_no_empty_groups() {
+    let alphabet = vec!['a', 'b'];
+    let counts = CooccurrenceCounter::new(alphabet.clone())
+        .offset_back(1)
+        .offset_front(2)
+        .count_text("abba")
+        .unwrap();
+
+    let per_distance = Grouping {
+        base: true,
+        found: true,
+        distance: false,
+    };
+    for grouping in [Grouping::FOUND, per_distance] {
+        let (normalized, summary) = handle_empty(
+            &counts,
+            SumOne.normalize(&counts, grouping, 1),
+            grouping,
+            EmptyPolicy::Fail,
+            1,
+        );
+        assert!(
+            summary.empty.is_empty(),
+            "{grouping}: {}",
+            summary.report(&alphabet, 1)
+        );
+        assert!(summary.degenerate.is_empty());
+        assert!(normalized.iter().all(|value| value.is_finite()));
+        assert!(normalized
+            .index_axis(Axis(2), 1)
+            .iter()
+            .all(|&value| value == 0.0));
+    }
+}
+
+#[test]
+fn legacy_alphabet_is_read_and_replaced() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("legacy.h5");
+    let file = hdf5::File::create(&path).unwrap();
+    let dataset = file
+        .new_dataset::<u64>()
+        .shape((2, 2, 3))
+        .create("normalized_data")
+        .unwrap();
+    // Schema version 1 stored the alphabet as ASCII and had no version
+    dataset
+        .new_attr::<VarLenAscii>()
+        .shape(())
+        .create("acceptable_types")
+        .unwrap()
+        .write_scalar(&VarLenAscii::from_ascii("ab").unwrap())
+        .unwrap();
+    assert_eq!(metadata::read_alphabet(&dataset).unwrap(), vec!['a', 'b']);
+
+    // Normalizing the file again writes the alphabet as UTF-8
+    metadata::write_alphabet(&dataset, &['a', '\u{e4}']).unwrap();
+    drop(file);
+    let file = hdf5::File::open(&path).unwrap();
+    let dataset = file.dataset("normalized_data").unwrap();
+    assert_eq!(
+        metadata::read_alphabet(&dataset).unwrap(),
+        vec!['a', '\u{e4}']
+    );
+    let version: u64 = dataset
+        .attr("schema_version")
+        .unwrap()
+        .read_scalar()
+        .unwrap();
+    assert_eq!(version, metadata::SCHEMA_VERSION);
+}